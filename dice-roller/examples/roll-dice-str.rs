@@ -1,14 +1,16 @@
 #[macro_use]
 extern crate log;
-use dice_roller::Dice;
+use dice_roller::{Dice, Die};
 
 fn main() {
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
     info!("Starting roll dice from str example");
 
-    let mut dice: Dice = Default::default();
-    let dice_list = ["1d6", "4d8", "2d10"];
-    dice.add_dice(&dice_list).unwrap();
+    let d20: Die = "d20".parse().unwrap();
+    let dice: Dice = "1d6 + 4d8".parse().unwrap();
+    let modified: Dice = "2d10-1".parse().unwrap();
+    let mut dice = dice + modified + d20 * 1;
+    info!("Rolling {}", dice);
 
-    println!("Result of dice roll: {}", dice.roll_dice());
+    println!("Result of dice roll: {}", dice.roll_dice().unwrap());
 }
@@ -0,0 +1,662 @@
+//! Static analysis of dice expressions.
+//!
+//! An [`Analysis`] gives the bounds, the expected value and the cost of an expression without
+//! rolling it, so that expressions too expensive to roll can be rejected beforehand.
+//!
+//! Bounds take the limits of the roller into account: a die can't explode more times than
+//! `max_explosions` allows, so `1d6!` can't total more than 606 with the default limits.
+
+use crate::distribution::reroll_weight;
+use crate::error::DiceError;
+use crate::expr::{
+    Aggregate, BinOp, Comparison, Condition, DiceTerm, ExplodeKind, Expr, Pool, Reroll, Statement,
+    StatementKind,
+};
+use crate::roller::Limits;
+use std::collections::BTreeMap;
+
+/// Maximum number of faces and binomial terms gone through for the means of kept dice of an
+/// expression.
+const MAX_WORK: u64 = 10_000_000;
+
+/// What is known about an expression before rolling it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// No total can be smaller.
+    pub min: i64,
+    /// No total can be larger.
+    pub max: i64,
+    /// Expected total, `None` if it can't be computed without rolling.
+    pub mean: Option<f64>,
+    /// Number of dice rolled, before rerolls and explosions.
+    pub dice: u64,
+    /// Cost of rolling the expression: the most dice it can roll, rerolls and explosions
+    /// included, weighted by the number of modifiers applied to them, plus one for each
+    /// number, operator and label. Rolling a die costs the same for any number of sides.
+    pub complexity: u64,
+}
+
+impl Analysis {
+    /// Analyzes an expression rolled under the given limits.
+    ///
+    /// The mean assumes that rerolls and explosions never reach their limits, and isn't
+    /// computed for divisions, for kept dice that explode or count successes, or for kept dice
+    /// once the faces and dice kept of the expression take too long to go through.
+    pub fn of(expr: &Expr, limits: &Limits) -> Result<Analysis, DiceError> {
+        limits.check(expr)?;
+        let mut work = MAX_WORK;
+        Analysis::of_checked(expr, limits, &mut work)
+    }
+
+    /// Analyzes a statement rolled under the given limits.
+    ///
+    /// The bounds of a repeated expression are the bounds of its aggregate, or of each roll if
+    /// it has none.
+    pub fn of_statement(statement: &Statement, limits: &Limits) -> Result<Analysis, DiceError> {
+        let repeat = match &statement.kind {
+            StatementKind::Roll(expr) => return Analysis::of(expr, limits),
+            StatementKind::Repeat(repeat) => repeat,
+        };
+        limits.check_statement(statement)?;
+        let mut work = MAX_WORK;
+        let roll = Analysis::of_checked(&repeat.expr, limits, &mut work)?;
+        let times = repeat.times as i64;
+        let (min, max, mean) = match repeat.aggregate {
+            Some(Aggregate::Sum) => (
+                roll.min.saturating_mul(times),
+                roll.max.saturating_mul(times),
+                roll.mean.map(|mean| mean * times as f64),
+            ),
+            Some(Aggregate::Count(_)) => (0, times, None),
+            Some(Aggregate::Max) | Some(Aggregate::Min) => (roll.min, roll.max, None),
+            None => (roll.min, roll.max, roll.mean),
+        };
+        Ok(Analysis {
+            min,
+            max,
+            mean,
+            dice: roll.dice * repeat.times as u64,
+            complexity: roll.complexity.saturating_mul(repeat.times as u64),
+        })
+    }
+
+    /// Analyzes an expression already checked against the limits, with `work` faces and
+    /// binomial terms left to go through for kept dice.
+    fn of_checked(expr: &Expr, limits: &Limits, work: &mut u64) -> Result<Analysis, DiceError> {
+        match expr {
+            Expr::Number(value) => Ok(Analysis {
+                min: *value,
+                max: *value,
+                mean: Some(*value as f64),
+                dice: 0,
+                complexity: 1,
+            }),
+            Expr::Dice(term) => Ok(Analysis::of_term(term, limits, work)),
+            Expr::Label(expr, _) => {
+                let inner = Analysis::of_checked(expr, limits, work)?;
+                Ok(Analysis {
+                    complexity: inner.complexity.saturating_add(1),
+                    ..inner
+                })
+            }
+            Expr::Neg(expr) => {
+                let inner = Analysis::of_checked(expr, limits, work)?;
+                Ok(Analysis {
+                    min: inner.max.saturating_neg(),
+                    max: inner.min.saturating_neg(),
+                    mean: inner.mean.map(|mean| -mean),
+                    complexity: inner.complexity.saturating_add(1),
+                    ..inner
+                })
+            }
+            Expr::Binary(..) => {
+                // Chains such as `1+2+3` are walked in a loop rather than recursively.
+                let (first, operations) = expr.chain();
+                let mut lhs = Analysis::of_checked(first, limits, work)?;
+                for (op, rhs) in operations {
+                    let rhs = Analysis::of_checked(rhs, limits, work)?;
+                    lhs = Analysis::binary(op, &lhs, &rhs)?;
+                }
+                Ok(lhs)
+            }
+        }
+    }
+
+    /// Analyzes an operation on two analyzed operands.
+    fn binary(op: BinOp, lhs: &Analysis, rhs: &Analysis) -> Result<Analysis, DiceError> {
+        let (min, max, mean) = match op {
+            BinOp::Add => (
+                lhs.min.saturating_add(rhs.min),
+                lhs.max.saturating_add(rhs.max),
+                lhs.mean.and_then(|a| rhs.mean.map(|b| a + b)),
+            ),
+            BinOp::Sub => (
+                lhs.min.saturating_sub(rhs.max),
+                lhs.max.saturating_sub(rhs.min),
+                lhs.mean.and_then(|a| rhs.mean.map(|b| a - b)),
+            ),
+            BinOp::Mul => {
+                let (min, max) = extremes(lhs, &[rhs.min, rhs.max], i64::saturating_mul);
+                // Both sides are rolled independently.
+                (min, max, lhs.mean.and_then(|a| rhs.mean.map(|b| a * b)))
+            }
+            BinOp::Div => {
+                if rhs.min == 0 && rhs.max == 0 {
+                    return Err(DiceError::DivisionByZero);
+                }
+                // The quotient is extreme for the divisors closest to zero or farthest
+                // from it, on each side of zero.
+                let divisors: Vec<i64> = [rhs.min, rhs.max, -1, 1]
+                    .iter()
+                    .copied()
+                    .filter(|&divisor| divisor != 0 && rhs.min <= divisor && divisor <= rhs.max)
+                    .collect();
+                let (min, max) =
+                    extremes(lhs, &divisors, |a, b| a.checked_div(b).unwrap_or(i64::MAX));
+                let mean = if lhs.min == lhs.max && rhs.min == rhs.max {
+                    Some(min as f64)
+                } else {
+                    None
+                };
+                (min, max, mean)
+            }
+        };
+        Ok(Analysis {
+            min,
+            max,
+            mean,
+            dice: lhs.dice + rhs.dice,
+            complexity: lhs
+                .complexity
+                .saturating_add(rhs.complexity)
+                .saturating_add(1),
+        })
+    }
+
+    /// Analyzes a dice term.
+    ///
+    /// Bounds and means are computed over runs of faces rather than face by face, so that they
+    /// take the same time for any number of sides, except for the means of kept dice.
+    fn of_term(term: &DiceTerm, limits: &Limits, work: &mut u64) -> Analysis {
+        let count = term.count as u64;
+        let die = Die::of(term);
+        let (lo, hi) = (term.min_face(), term.max_face());
+
+        // Each die that can explode rolls at most `max_explosions` extra dice.
+        let explode = term
+            .explode
+            .filter(|explode| die.chance(explode.trigger(term)) > 0.0);
+        let explosions = explode.map_or(0, |_| count * limits.max_explosions as u64);
+        let kind = explode.map(|explode| explode.kind);
+        let penalty = if kind == Some(ExplodeKind::Penetrate) {
+            1
+        } else {
+            0
+        };
+        let compound = kind == Some(ExplodeKind::Compound);
+        let kept = |dice: u64| match term.keep {
+            Some(keep) => dice - keep.discarded(dice as usize).0 as u64,
+            None => dice,
+        };
+        let fewest = kept(count);
+        let most = if compound {
+            fewest
+        } else {
+            kept(count + explosions)
+        };
+
+        let (min, max) = match term.pool {
+            None if term.keep.is_none() || compound => (
+                fewest as i128 * lo as i128 + explosions as i128 * (lo - penalty).min(0) as i128,
+                fewest as i128 * hi as i128 + explosions as i128 * (hi - penalty).max(0) as i128,
+            ),
+            None => {
+                let extra = if explosions > 0 { penalty } else { 0 };
+                bounds(fewest, most, lo - extra, hi)
+            }
+            Some(pool) => {
+                let (lowest, highest) = if compound {
+                    // A compounded die can total any value above its faces.
+                    let failure = if pool.failure.is_some() { -1 } else { 0 };
+                    let success = if pool.double.is_some() { 2 } else { 1 };
+                    (failure, success)
+                } else {
+                    let mut scores = die.scores(pool);
+                    scores.extend(die.shifted(penalty).scores(pool));
+                    scores
+                        .into_iter()
+                        .fold((i64::MAX, i64::MIN), |(lowest, highest), score| {
+                            (lowest.min(score), highest.max(score))
+                        })
+                };
+                bounds(fewest, most, lowest, highest)
+            }
+        };
+
+        let rolled = match term.reroll {
+            Some(reroll) => die.rerolled(reroll),
+            None => Some(die.clone()),
+        };
+        let mean = rolled.and_then(|rolled| {
+            let die_mean = |die: &Die| match term.pool {
+                Some(pool) => die.score_mean(pool),
+                None => die.mean(),
+            };
+            match (explode, term.keep) {
+                (None, None) => Some(count as f64 * die_mean(&rolled)),
+                (Some(explode), None) if !(compound && term.pool.is_some()) => {
+                    let trigger = explode.trigger(term);
+                    let again = die.chance(trigger);
+                    if again >= 1.0 {
+                        return None;
+                    }
+                    let extra = rolled.chance(trigger) / (1.0 - again);
+                    Some(
+                        count as f64
+                            * (die_mean(&rolled) + extra * die_mean(&die.shifted(penalty))),
+                    )
+                }
+                (None, Some(keep)) if term.pool.is_none() => {
+                    let (discarded, lowest_first) = keep.discarded(count as usize);
+                    let kept = count - discarded as u64;
+                    let cost = rolled.len().saturating_mul(1 + kept.min(count - kept));
+                    *work = work.checked_sub(cost)?;
+                    let faces: Vec<(i64, f64)> = rolled.faces().collect();
+                    Some(kept_mean(&faces, count, kept, lowest_first))
+                }
+                _ => None,
+            }
+        });
+
+        let rerolls = term.reroll.map_or(0, |reroll| {
+            if reroll.recursive {
+                count * limits.max_rerolls as u64
+            } else {
+                count
+            }
+        });
+        let modifiers = [
+            term.reroll.is_some(),
+            term.explode.is_some(),
+            term.keep.is_some(),
+            term.pool.is_some(),
+            term.crit_success.is_some() || term.crit_failure.is_some(),
+        ];
+        let weight = 1 + modifiers.iter().filter(|&&modifier| modifier).count() as u64;
+        Analysis {
+            min: clamp(min),
+            max: clamp(max),
+            mean,
+            dice: count,
+            complexity: (count + rerolls + explosions).saturating_mul(weight),
+        }
+    }
+}
+
+/// Returns the smallest and largest results of `op` applied to the bounds of `lhs` and each of
+/// `values`.
+fn extremes<F: Fn(i64, i64) -> i64>(lhs: &Analysis, values: &[i64], op: F) -> (i64, i64) {
+    let results: Vec<i64> = [lhs.min, lhs.max]
+        .iter()
+        .flat_map(|&a| values.iter().map(move |&b| (a, b)))
+        .map(|(a, b)| op(a, b))
+        .collect();
+    (
+        results.iter().copied().min().unwrap_or(0),
+        results.iter().copied().max().unwrap_or(0),
+    )
+}
+
+/// Returns the bounds of the sum of between `fewest` and `most` values, each between `lowest`
+/// and `highest`.
+fn bounds(fewest: u64, most: u64, lowest: i64, highest: i64) -> (i128, i128) {
+    let min = (if lowest <= 0 { most } else { fewest }) as i128 * lowest as i128;
+    let max = (if highest >= 0 { most } else { fewest }) as i128 * highest as i128;
+    (min, max)
+}
+
+/// Clamps a bound to a signed 64-bit integer.
+fn clamp(value: i128) -> i64 {
+    value.max(i64::MIN as i128).min(i64::MAX as i128) as i64
+}
+
+/// The values a die of a term can show, as runs of consecutive values of equal probability.
+#[derive(Debug, Clone)]
+struct Die {
+    /// First value, last value and probability of each value of a run, in increasing order.
+    runs: Vec<(i64, i64, f64)>,
+}
+
+impl Die {
+    /// Returns the values of a die of a term, a single run for a plain die.
+    fn of(term: &DiceTerm) -> Die {
+        let sides = term.sides as f64;
+        if term.fudge.is_none() {
+            return Die {
+                runs: vec![(1, term.sides as i64, 1.0 / sides)],
+            };
+        }
+        let mut faces = BTreeMap::new();
+        for side in 1..=term.sides {
+            *faces.entry(term.face(side)).or_insert(0.0) += 1.0 / sides;
+        }
+        Die {
+            runs: faces
+                .into_iter()
+                .map(|(face, probability)| (face, face, probability))
+                .collect(),
+        }
+    }
+
+    /// Returns the number of values of the die.
+    fn len(&self) -> u64 {
+        self.runs
+            .iter()
+            .map(|&(first, last, _)| (last - first) as u64 + 1)
+            .sum()
+    }
+
+    /// Returns every value of the die with its probability, in increasing order.
+    fn faces(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        self.runs.iter().flat_map(|&(first, last, probability)| {
+            (first..=last).map(move |face| (face, probability))
+        })
+    }
+
+    /// Returns the die showing the values of this one minus `offset`.
+    fn shifted(&self, offset: i64) -> Die {
+        Die {
+            runs: self
+                .runs
+                .iter()
+                .map(|&(first, last, probability)| (first - offset, last - offset, probability))
+                .collect(),
+        }
+    }
+
+    /// Returns the probability that the die shows a value between `low` and `high`.
+    fn between(&self, (low, high): (i64, i64)) -> f64 {
+        self.runs
+            .iter()
+            .map(|&(first, last, probability)| {
+                let (first, last) = (first.max(low), last.min(high));
+                if first <= last {
+                    ((last - first) as f64 + 1.0) * probability
+                } else {
+                    0.0
+                }
+            })
+            .sum()
+    }
+
+    /// Returns the probability that the die shows a value matching `condition`.
+    fn chance(&self, condition: Condition) -> f64 {
+        self.between(range(condition))
+    }
+
+    /// Returns the expected value of the die.
+    fn mean(&self) -> f64 {
+        self.runs
+            .iter()
+            .map(|&(first, last, probability)| {
+                ((last - first) as f64 + 1.0) * probability * (first as f64 + last as f64) / 2.0
+            })
+            .sum()
+    }
+
+    /// Returns the expected score of the die in a dice pool.
+    fn score_mean(&self, pool: Pool) -> f64 {
+        let success = range(pool.success);
+        let double = pool.double.map_or(0.0, |double| {
+            let double = range(double);
+            self.between((success.0.max(double.0), success.1.min(double.1)))
+        });
+        let failure = pool.failure.map_or(0.0, |failure| self.chance(failure));
+        self.between(success) + double - failure
+    }
+
+    /// Returns every score the die can have in a dice pool, and maybe more.
+    ///
+    /// Scores only change at the values of the conditions of the pool, so the values of the
+    /// die around them and at the ends of its runs have every score.
+    fn scores(&self, pool: Pool) -> Vec<i64> {
+        let values: Vec<i64> = [Some(pool.success), pool.double, pool.failure]
+            .iter()
+            .flatten()
+            .flat_map(|condition| {
+                vec![
+                    condition.value.saturating_sub(1),
+                    condition.value,
+                    condition.value.saturating_add(1),
+                ]
+            })
+            .collect();
+        let mut scores = Vec::new();
+        for &(first, last, _) in &self.runs {
+            let inside = values
+                .iter()
+                .copied()
+                .filter(|&value| first <= value && value <= last);
+            scores.extend(
+                vec![first, last]
+                    .into_iter()
+                    .chain(inside)
+                    .map(|value| pool.score(value)),
+            );
+        }
+        scores
+    }
+
+    /// Returns the die rerolled once or until it no longer matches the reroll condition,
+    /// `None` if every value is rerolled until it no longer matches.
+    fn rerolled(&self, reroll: Reroll) -> Option<Die> {
+        let (low, high) = range(reroll.condition);
+        let weight = reroll_weight(reroll, self.between((low, high))).ok()?;
+        let mut runs = Vec::with_capacity(self.runs.len() + 2);
+        for &(first, last, probability) in &self.runs {
+            // Values below and above the reroll condition are weighted alike.
+            if first < low {
+                runs.push((first, last.min(low - 1), weight(false, probability)));
+            }
+            if first.max(low) <= last.min(high) {
+                runs.push((first.max(low), last.min(high), weight(true, probability)));
+            }
+            if high < last {
+                runs.push((first.max(high + 1), last, weight(false, probability)));
+            }
+        }
+        Some(Die { runs })
+    }
+}
+
+/// Returns the smallest and largest values matching a condition, the smallest being larger
+/// than the largest if none does.
+fn range(condition: Condition) -> (i64, i64) {
+    let value = condition.value;
+    match condition.comparison {
+        Comparison::Equal => (value, value),
+        Comparison::Greater => value.checked_add(1).map_or((1, 0), |low| (low, i64::MAX)),
+        Comparison::GreaterOrEqual => (value, i64::MAX),
+        Comparison::Less => value.checked_sub(1).map_or((1, 0), |high| (i64::MIN, high)),
+        Comparison::LessOrEqual => (i64::MIN, value),
+    }
+}
+
+/// Returns the expected sum of the `kept` highest of `count` dice, or of the lowest ones if
+/// `highest` is false.
+///
+/// The sum of the highest dice is the lowest face times the dice kept, plus for each higher
+/// face the step from the previous face times the number of kept dice reaching it, which is
+/// the number of dice reaching it capped by the dice kept.
+fn kept_mean(faces: &[(i64, f64)], count: u64, kept: u64, highest: bool) -> f64 {
+    let mut faces = faces.to_vec();
+    if !highest {
+        faces = faces
+            .into_iter()
+            .rev()
+            .map(|(face, probability)| (-face, probability))
+            .collect();
+    }
+    let mut reaching = 1.0;
+    let mut sum = kept as f64 * faces[0].0 as f64;
+    for window in faces.windows(2) {
+        reaching -= window[0].1;
+        let step = (window[1].0 - window[0].0) as f64;
+        sum += step * capped_binomial_mean(count, kept, reaching);
+    }
+    if highest {
+        sum
+    } else {
+        -sum
+    }
+}
+
+/// Returns the expected value of `min(cap, B)` where `B` follows a binomial distribution of
+/// `trials` trials with a success `probability`.
+fn capped_binomial_mean(trials: u64, cap: u64, probability: f64) -> f64 {
+    if cap == 0 || probability <= 0.0 {
+        return 0.0;
+    }
+    if probability >= 1.0 || cap >= trials {
+        return cap.min(trials) as f64 * probability.min(1.0);
+    }
+    let (n, k) = (trials as f64, cap as f64);
+    let (success, failure) = (probability.ln(), (1.0 - probability).ln());
+    let mass = |i: f64, ln_binomial: f64| (ln_binomial + i * success + (n - i) * failure).exp();
+    if cap <= trials - cap {
+        // min(k, B) = k - (k - B) when B < k.
+        let mut ln_binomial = 0.0;
+        let mut below = 0.0;
+        for i in 0..cap {
+            let i = i as f64;
+            if i > 0.0 {
+                ln_binomial += ((n - i + 1.0) / i).ln();
+            }
+            below += (k - i) * mass(i, ln_binomial);
+        }
+        k - below
+    } else {
+        // min(k, B) = B - (B - k) when B > k.
+        let mut ln_binomial = 0.0;
+        let mut above = 0.0;
+        for i in (cap + 1..=trials).rev() {
+            let i = i as f64;
+            if i < n {
+                ln_binomial += ((i + 1.0) / (n - i)).ln();
+            }
+            above += (i - k) * mass(i, ln_binomial);
+        }
+        n * probability - above
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distribution::Distribution;
+    use crate::parser::{parse, parse_statement};
+
+    fn analysis(input: &str) -> Analysis {
+        Analysis::of(&parse(input).unwrap(), &Limits::default()).unwrap()
+    }
+
+    fn assert_mean(input: &str) {
+        let expected = Distribution::of(&parse(input).unwrap()).unwrap().mean();
+        let mean = analysis(input).mean.unwrap();
+        assert!(
+            (mean - expected).abs() < 1e-9,
+            "mean of {} is {}, not {}",
+            input,
+            mean,
+            expected
+        );
+    }
+
+    #[test]
+    fn bounds_and_mean() {
+        let result = analysis("2d6 + 3");
+        assert_eq!((result.min, result.max), (5, 15));
+        assert_eq!(result.mean, Some(10.0));
+        assert_eq!(result.dice, 2);
+        let result = analysis("4d6kh3 * -2");
+        assert_eq!((result.min, result.max), (-36, -6));
+        assert_eq!(analysis("4dF").min, -4);
+        assert_eq!(
+            (analysis("10d10>=8f1").min, analysis("10d10>=8").max),
+            (-10, 10)
+        );
+        assert_eq!(
+            (analysis("(1d6+1)/2").min, analysis("(1d6+1)/2").max),
+            (1, 3)
+        );
+        assert_eq!(analysis("(1d6+1)/2").mean, None);
+        for input in &[
+            "4d6kh3",
+            "2d20kl1",
+            "5d10dl2",
+            "3d6r1",
+            "2d8rr<3",
+            "10d10>=8dbl10",
+            "10d10>=8f1r1",
+            "3d10rr>8",
+            "4dF",
+            "4dF.1r<0",
+            "3d6r=3kh2",
+        ] {
+            assert_mean(input);
+        }
+        assert_eq!(
+            Analysis::of(&parse("1d6/(1d2-1d2)*0/0").unwrap(), &Limits::default()),
+            Err(DiceError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn bounds_of_explosions() {
+        let result = analysis("2d6!");
+        assert_eq!((result.min, result.max), (2, 1212));
+        assert!((result.mean.unwrap() - 8.4).abs() < 1e-9);
+        let result = analysis("1d6!p");
+        assert_eq!(result.max, 506);
+        assert!((result.mean.unwrap() - 3.5 - 0.2 * 2.5).abs() < 1e-9);
+        assert_eq!(analysis("3d6!>7").max, 18);
+        let result = analysis("5d10>=8!");
+        assert_eq!((result.min, result.max), (0, 505));
+        assert!((result.mean.unwrap() - 5.0 * 0.3 / 0.9).abs() < 1e-9);
+        let result = analysis("1d1000000kh1");
+        assert!((result.mean.unwrap() - 500_000.5).abs() < 1e-3);
+        // Kept dice of too many faces to go through have no mean.
+        let result = analysis(&["1000d1000000kh500"; 3].join("+"));
+        assert_eq!(result.max, 3 * 500_000_000);
+        assert_eq!(result.mean, None);
+        let limits = Limits {
+            max_explosions: 2,
+            ..Default::default()
+        };
+        let result = Analysis::of(&parse("1d1!").unwrap(), &limits).unwrap();
+        assert_eq!((result.min, result.max, result.mean), (1, 3, None));
+        let result = Analysis::of(&parse("200d1!").unwrap(), &limits).unwrap();
+        assert_eq!((result.min, result.max), (200, 600));
+    }
+
+    #[test]
+    fn complexity() {
+        assert_eq!(analysis("2d6 + 3").complexity, 4);
+        assert_eq!(analysis("4d6kh3").complexity, 8);
+        assert_eq!(analysis("2d6[fire]").complexity, 3);
+        assert_eq!(analysis("1000000d1000!").complexity, 202_000_000);
+        assert_eq!(analysis("1000d6rr1").complexity, 202_000);
+        assert_eq!(analysis("1d1000000").complexity, 1);
+        let result = analysis(&["1d1000000"; 100].join("+"));
+        assert_eq!(result.complexity, 199);
+        assert_eq!(result.mean, Some(100.0 * 500_000.5));
+        let statement = parse_statement("6x 4d6kh3 sum").unwrap();
+        let result = Analysis::of_statement(&statement, &Limits::default()).unwrap();
+        assert_eq!((result.min, result.max, result.dice), (18, 108, 24));
+        assert_eq!(result.complexity, 48);
+        assert_eq!(
+            Analysis::of(&parse("1d2000000").unwrap(), &Limits::default()),
+            Err(DiceError::SidesOutOfRange(2_000_000))
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! Dice with custom faces, such as the symbol dice of narrative games.
+//!
+//! A [`CustomDie`] is a list of [`Face`]s, each with a label, a numeric value and any number of
+//! symbols. Rolling a [`CustomPool`] tallies the symbols of every face rolled, then applies its
+//! cancellation rules, for example a success cancelling a failure.
+
+use crate::error::DiceError;
+use crate::roller::Limits;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A face of a custom die.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Face {
+    pub label: String,
+    /// Value added to the total of the pool, zero for faces that only carry symbols.
+    pub value: i64,
+    /// Number of each symbol shown on the face.
+    pub symbols: BTreeMap<String, u32>,
+}
+
+/// A die with arbitrary faces, each equally likely.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomDie {
+    pub name: String,
+    faces: Vec<Face>,
+}
+
+/// A cancellation rule: each `symbol` removes one `cancels`, until either runs out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cancel {
+    pub symbol: String,
+    pub cancels: String,
+}
+
+/// A pool of custom dice rolled together.
+#[derive(Debug, Default, PartialEq)]
+pub struct CustomPool {
+    dice: Vec<(CustomDie, u32)>,
+    /// Cancellation rules, applied in order once every die is rolled.
+    pub rules: Vec<Cancel>,
+    pub limits: Limits,
+}
+
+/// The outcome of a single custom die.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomDieResult {
+    /// Name of the die that was rolled.
+    pub die: String,
+    pub face: Face,
+}
+
+/// The outcome of a pool of custom dice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolResult {
+    /// Every die rolled, in the order they were added to the pool.
+    pub dice: Vec<CustomDieResult>,
+    /// Symbols rolled, before cancellation.
+    pub rolled: BTreeMap<String, u32>,
+    /// Symbols left after cancellation, symbols cancelled down to zero being removed.
+    pub symbols: BTreeMap<String, u32>,
+    /// Sum of the values of the faces.
+    pub total: i64,
+}
+
+impl Face {
+    /// Creates a face with a label and no value or symbol.
+    pub fn new(label: &str) -> Face {
+        Face {
+            label: label.to_string(),
+            value: 0,
+            symbols: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a face showing a number, labelled with that number.
+    pub fn number(value: i64) -> Face {
+        Face {
+            value,
+            ..Face::new(&value.to_string())
+        }
+    }
+
+    /// Adds `count` of a symbol to the face.
+    pub fn with_symbol(mut self, symbol: &str, count: u32) -> Face {
+        *self.symbols.entry(symbol.to_string()).or_insert(0) += count;
+        self
+    }
+
+    /// Sets the value of the face.
+    pub fn with_value(mut self, value: i64) -> Face {
+        self.value = value;
+        self
+    }
+}
+
+impl CustomDie {
+    /// Creates a die from its faces, which can't be empty.
+    pub fn new(name: &str, faces: Vec<Face>) -> Result<CustomDie, DiceError> {
+        if faces.is_empty() {
+            return Err(DiceError::SidesOutOfRange(0));
+        }
+        Ok(CustomDie {
+            name: name.to_string(),
+            faces,
+        })
+    }
+
+    /// Returns the faces of the die.
+    pub fn faces(&self) -> &[Face] {
+        &self.faces
+    }
+
+    /// Rolls the die once.
+    pub fn roll_die(&self) -> &Face {
+        self.roll_with(&mut rand::thread_rng())
+    }
+
+    /// Rolls the die once using the given random number generator.
+    pub fn roll_with<R: Rng + ?Sized>(&self, rng: &mut R) -> &Face {
+        &self.faces[rng.gen_range(0, self.faces.len())]
+    }
+}
+
+impl Cancel {
+    /// Creates a rule where each `symbol` cancels one `cancels`.
+    pub fn new(symbol: &str, cancels: &str) -> Cancel {
+        Cancel {
+            symbol: symbol.to_string(),
+            cancels: cancels.to_string(),
+        }
+    }
+}
+
+impl CustomPool {
+    /// Creates an empty pool with the given cancellation rules.
+    pub fn with_rules(rules: Vec<Cancel>) -> CustomPool {
+        CustomPool {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the number of dice in the pool.
+    pub fn len(&self) -> u64 {
+        self.dice.iter().map(|&(_, count)| count as u64).sum()
+    }
+
+    /// Returns true if the pool has no dice.
+    pub fn is_empty(&self) -> bool {
+        self.dice.is_empty()
+    }
+
+    /// Adds `count` copies of a die to the pool.
+    pub fn add_dice(&mut self, die: &CustomDie, count: u32) -> Result<(), DiceError> {
+        if count == 0 {
+            return Err(DiceError::ZeroDice);
+        }
+        if self.len() + count as u64 > self.limits.max_dice {
+            return Err(DiceError::TooManyDice);
+        }
+        log::info!("Adding {}d{} to pool", count, die.name);
+        match self.dice.last_mut() {
+            Some((last, total)) if last == die => *total += count,
+            _ => self.dice.push((die.clone(), count)),
+        }
+        Ok(())
+    }
+
+    /// Rolls every die of the pool and tallies their symbols.
+    pub fn roll(&self) -> Result<PoolResult, DiceError> {
+        self.roll_with(&mut rand::thread_rng())
+    }
+
+    /// Rolls every die of the pool using the given random number generator.
+    pub fn roll_with<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<PoolResult, DiceError> {
+        let mut dice = Vec::new();
+        let mut rolled = BTreeMap::new();
+        let mut total: i64 = 0;
+        for (die, count) in &self.dice {
+            for _ in 0..*count {
+                let face = die.roll_with(rng);
+                for (symbol, shown) in &face.symbols {
+                    *rolled.entry(symbol.clone()).or_insert(0) += shown;
+                }
+                total = total.checked_add(face.value).ok_or(DiceError::Overflow)?;
+                dice.push(CustomDieResult {
+                    die: die.name.clone(),
+                    face: face.clone(),
+                });
+            }
+        }
+        let symbols = cancel(&rolled, &self.rules);
+        Ok(PoolResult {
+            dice,
+            rolled,
+            symbols,
+            total,
+        })
+    }
+}
+
+impl PoolResult {
+    /// Returns how many of a symbol are left after cancellation.
+    pub fn count(&self, symbol: &str) -> u32 {
+        self.symbols.get(symbol).copied().unwrap_or(0)
+    }
+}
+
+/// Applies cancellation rules in order to tallied symbols.
+fn cancel(rolled: &BTreeMap<String, u32>, rules: &[Cancel]) -> BTreeMap<String, u32> {
+    let mut symbols = rolled.clone();
+    for rule in rules {
+        let count = |symbol: &str| symbols.get(symbol).copied().unwrap_or(0);
+        let cancelled = count(&rule.symbol).min(count(&rule.cancels));
+        for symbol in &[&rule.symbol, &rule.cancels] {
+            if let Some(count) = symbols.get_mut(symbol.as_str()) {
+                *count = count.saturating_sub(cancelled);
+            }
+        }
+    }
+    symbols.retain(|_, count| *count > 0);
+    symbols
+}
+
+impl fmt::Display for PoolResult {
+    /// Writes the faces rolled followed by the symbols left, e.g.
+    /// `[Success, Failure, Success Advantage] = 1 Success, 1 Advantage`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let faces: Vec<&str> = self
+            .dice
+            .iter()
+            .map(|die| die.face.label.as_str())
+            .collect();
+        write!(f, "[{}] = ", faces.join(", "))?;
+        if self.symbols.is_empty() {
+            return write!(f, "{}", self.total);
+        }
+        let symbols: Vec<String> = self
+            .symbols
+            .iter()
+            .map(|(symbol, count)| format!("{} {}", count, symbol))
+            .collect();
+        write!(f, "{}", symbols.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn hit_die() -> CustomDie {
+        CustomDie::new(
+            "hit",
+            vec![
+                Face::new("Hit").with_symbol("hit", 1),
+                Face::new("Hit").with_symbol("hit", 1),
+                Face::new("Crit").with_symbol("hit", 2),
+                Face::new("Miss").with_symbol("miss", 1),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn custom_die_faces() {
+        assert_eq!(
+            CustomDie::new("empty", Vec::new()),
+            Err(DiceError::SidesOutOfRange(0))
+        );
+        let die = hit_die();
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            assert!(die.faces().contains(die.roll_with(&mut rng)));
+        }
+        let face = Face::number(3).with_symbol("star", 1);
+        assert_eq!((face.label.as_str(), face.value), ("3", 3));
+    }
+
+    #[test]
+    fn pool_tallies_symbols() {
+        let mut pool = CustomPool::default();
+        pool.add_dice(&hit_die(), 10).unwrap();
+        assert_eq!(pool.add_dice(&hit_die(), 0), Err(DiceError::ZeroDice));
+        let result = pool.roll().unwrap();
+        assert_eq!(result.dice.len(), 10);
+        let hits: u32 = result
+            .dice
+            .iter()
+            .map(|die| die.face.symbols.get("hit").copied().unwrap_or(0))
+            .sum();
+        assert_eq!(result.count("hit"), hits);
+        assert_eq!(result.rolled, result.symbols);
+    }
+
+    #[test]
+    fn pool_cancels_symbols() {
+        let mut rolled = BTreeMap::new();
+        rolled.insert("success".to_string(), 3);
+        rolled.insert("failure".to_string(), 1);
+        rolled.insert("advantage".to_string(), 1);
+        rolled.insert("threat".to_string(), 2);
+        let rules = [
+            Cancel::new("success", "failure"),
+            Cancel::new("advantage", "threat"),
+        ];
+        let symbols = cancel(&rolled, &rules);
+        let expected: BTreeMap<String, u32> =
+            vec![("success".to_string(), 2), ("threat".to_string(), 1)]
+                .into_iter()
+                .collect();
+        assert_eq!(symbols, expected);
+    }
+
+    #[test]
+    fn display_pool() {
+        let mut pool = CustomPool::with_rules(vec![Cancel::new("hit", "miss")]);
+        let die = CustomDie::new("blank", vec![Face::new("Miss").with_symbol("miss", 1)]).unwrap();
+        pool.add_dice(&die, 2).unwrap();
+        let crit = CustomDie::new("crit", vec![Face::new("Crit").with_symbol("hit", 3)]).unwrap();
+        pool.add_dice(&crit, 1).unwrap();
+        assert_eq!(pool.len(), 3);
+        let result = pool.roll().unwrap();
+        assert_eq!(result.to_string(), "[Miss, Miss, Crit] = 1 hit");
+        let die = CustomDie::new("d2", vec![Face::number(2)]).unwrap();
+        let mut pool = CustomPool::default();
+        pool.add_dice(&die, 3).unwrap();
+        assert_eq!(pool.roll().unwrap().to_string(), "[2, 2, 2] = 6");
+    }
+}
@@ -0,0 +1,557 @@
+//! Exact probability distributions of dice expressions.
+//!
+//! Distributions are computed by convolving the probability mass functions of the terms of an
+//! expression, so they are exact up to floating point rounding and don't involve any sampling.
+
+use crate::error::DiceError;
+use crate::expr::{BinOp, DiceTerm, Expr, Keep, Reroll};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Maximum number of distinct values a distribution may span.
+const MAX_SUPPORT: usize = 1_000_000;
+
+/// Maximum number of multiplications a single convolution may take.
+const MAX_WORK: usize = 100_000_000;
+
+/// Probability mass function of an integer random variable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    /// Smallest value of the support.
+    offset: i64,
+    /// `probabilities[i]` is the probability of the value `offset + i`, the largest value of
+    /// the support fitting in an `i64`.
+    probabilities: Vec<f64>,
+}
+
+impl Distribution {
+    /// Returns the distribution of a value that is always `value`.
+    pub fn constant(value: i64) -> Distribution {
+        Distribution {
+            offset: value,
+            probabilities: vec![1.0],
+        }
+    }
+
+    /// Returns the distribution of a single die with faces from 1 to `sides`.
+    pub fn uniform(sides: u32) -> Distribution {
+        let sides = sides.max(1) as usize;
+        Distribution {
+            offset: 1,
+            probabilities: vec![1.0 / sides as f64; sides],
+        }
+    }
+
+    /// Computes the exact distribution of the total of an expression.
+    ///
+    /// Sums, arithmetic, rerolls, keep and drop modifiers and dice pools are supported.
+    /// Exploding dice have an unbounded number of outcomes and are rejected.
+    pub fn of(expr: &Expr) -> Result<Distribution, DiceError> {
+        match expr {
+            Expr::Number(value) => Ok(Distribution::constant(*value)),
+            Expr::Dice(term) => Distribution::of_term(term),
+            Expr::Neg(expr) => Distribution::of(expr)?.negate(),
+            Expr::Label(expr, _) => Distribution::of(expr),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = Distribution::of(lhs)?;
+                let rhs = Distribution::of(rhs)?;
+                match op {
+                    BinOp::Add => lhs.add(&rhs),
+                    BinOp::Sub => lhs.add(&rhs.negate()?),
+                    BinOp::Mul => lhs.combine(&rhs, &[rhs.min(), rhs.max()], |a, b| {
+                        a.checked_mul(b).ok_or(DiceError::Overflow)
+                    }),
+                    BinOp::Div => {
+                        if rhs.probability(0) > 0.0 {
+                            return Err(DiceError::DivisionByZero);
+                        }
+                        // The quotient is extreme for the divisors closest to zero or farthest
+                        // from it, on each side of zero.
+                        let divisors: Vec<i64> = [rhs.min(), rhs.max(), -1, 1]
+                            .iter()
+                            .copied()
+                            .filter(|&divisor| {
+                                divisor != 0 && rhs.min() <= divisor && divisor <= rhs.max()
+                            })
+                            .collect();
+                        lhs.combine(&rhs, &divisors, |a, b| {
+                            a.checked_div(b).ok_or(DiceError::Overflow)
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    /// Computes the distribution of the total of a dice term.
+    fn of_term(term: &DiceTerm) -> Result<Distribution, DiceError> {
+        if term.explode.is_some() {
+            return Err(DiceError::Unsupported(
+                "exploding dice have no exact distribution",
+            ));
+        }
+        if term.sides as usize > MAX_SUPPORT {
+            return Err(DiceError::Unsupported(
+                "distribution has too many outcomes to compute",
+            ));
+        }
+        let mut die = match term.fudge {
+            Some(_) => Distribution::from_outcomes(
+                (1..=term.sides).map(|side| (term.face(side), 1.0 / term.sides as f64)),
+            )?,
+            None => Distribution::uniform(term.sides),
+        };
+        if let Some(reroll) = term.reroll {
+            die = die.reroll(reroll)?;
+        }
+        match (term.keep, term.pool) {
+            (Some(_), Some(_)) => Err(DiceError::Unsupported(
+                "kept dice pools have no exact distribution",
+            )),
+            (Some(keep), None) => die.keep(term.count, keep),
+            (None, Some(pool)) => die.map(|face| pool.score(face)).repeat(term.count),
+            (None, None) => die.repeat(term.count),
+        }
+    }
+
+    /// Returns the smallest possible value.
+    pub fn min(&self) -> i64 {
+        self.offset
+    }
+
+    /// Returns the largest possible value.
+    pub fn max(&self) -> i64 {
+        self.offset + (self.probabilities.len() as i64 - 1)
+    }
+
+    /// Returns the probability of the value `value`.
+    pub fn probability(&self, value: i64) -> f64 {
+        usize::try_from(value as i128 - self.offset as i128)
+            .ok()
+            .and_then(|i| self.probabilities.get(i))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Returns the probability of a value greater than or equal to `target`.
+    pub fn at_least(&self, target: i64) -> f64 {
+        self.iter()
+            .filter(|&(value, _)| value >= target)
+            .map(|(_, probability)| probability)
+            .sum()
+    }
+
+    /// Returns the probability of a value less than or equal to `target`.
+    pub fn at_most(&self, target: i64) -> f64 {
+        self.iter()
+            .filter(|&(value, _)| value <= target)
+            .map(|(_, probability)| probability)
+            .sum()
+    }
+
+    /// Returns the expected value.
+    pub fn mean(&self) -> f64 {
+        self.iter()
+            .map(|(value, probability)| value as f64 * probability)
+            .sum()
+    }
+
+    /// Returns the variance.
+    pub fn variance(&self) -> f64 {
+        let mean = self.mean();
+        self.iter()
+            .map(|(value, probability)| (value as f64 - mean).powi(2) * probability)
+            .sum()
+    }
+
+    /// Returns the standard deviation.
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Returns the smallest value whose cumulative probability reaches `percentile`, given
+    /// between 0 and 1.
+    pub fn percentile(&self, percentile: f64) -> i64 {
+        let mut cumulative = 0.0;
+        for (value, probability) in self.iter() {
+            cumulative += probability;
+            // Tolerate the rounding errors accumulated by the convolutions.
+            if cumulative >= percentile - 1e-12 {
+                return value;
+            }
+        }
+        self.max()
+    }
+
+    /// Returns the median value.
+    pub fn median(&self) -> i64 {
+        self.percentile(0.5)
+    }
+
+    /// Returns every value of the support along with its probability, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        let offset = self.offset;
+        self.probabilities
+            .iter()
+            .enumerate()
+            .map(move |(i, &probability)| (offset + i as i64, probability))
+    }
+
+    /// Builds a distribution from a list of values and probabilities.
+    fn from_outcomes<I>(outcomes: I) -> Result<Distribution, DiceError>
+    where
+        I: IntoIterator<Item = (i64, f64)>,
+    {
+        let outcomes: Vec<(i64, f64)> = outcomes
+            .into_iter()
+            .filter(|&(_, probability)| probability > 0.0)
+            .collect();
+        let min = outcomes.iter().map(|&(value, _)| value).min().unwrap_or(0);
+        let max = outcomes.iter().map(|&(value, _)| value).max().unwrap_or(0);
+        if (max as i128 - min as i128) >= MAX_SUPPORT as i128 {
+            return Err(DiceError::Unsupported(
+                "distribution has too many outcomes to compute",
+            ));
+        }
+        let mut probabilities = vec![0.0; (max - min) as usize + 1];
+        for (value, probability) in outcomes {
+            probabilities[(value - min) as usize] += probability;
+        }
+        Ok(Distribution {
+            offset: min,
+            probabilities,
+        })
+    }
+
+    /// Returns the distribution of the opposite value.
+    fn negate(&self) -> Result<Distribution, DiceError> {
+        // The opposite of the smallest value must fit too.
+        self.offset.checked_neg().ok_or(DiceError::Overflow)?;
+        let mut probabilities = self.probabilities.clone();
+        probabilities.reverse();
+        Ok(Distribution {
+            offset: -self.max(),
+            probabilities,
+        })
+    }
+
+    /// Returns the distribution of a value transformed by `f`.
+    fn map<F: Fn(i64) -> i64>(&self, f: F) -> Distribution {
+        Distribution::from_outcomes(
+            self.iter()
+                .map(|(value, probability)| (f(value), probability)),
+        )
+        .expect("a mapped die has as many outcomes as the die")
+    }
+
+    /// Returns the distribution of the sum of two independent values.
+    fn add(&self, other: &Distribution) -> Result<Distribution, DiceError> {
+        if self.probabilities.len() * other.probabilities.len() > MAX_WORK {
+            return Err(DiceError::Unsupported(
+                "distribution has too many outcomes to compute",
+            ));
+        }
+        let offset = self
+            .offset
+            .checked_add(other.offset)
+            .ok_or(DiceError::Overflow)?;
+        self.max()
+            .checked_add(other.max())
+            .ok_or(DiceError::Overflow)?;
+        let mut probabilities = vec![0.0; self.probabilities.len() + other.probabilities.len() - 1];
+        for (i, a) in self.probabilities.iter().enumerate() {
+            for (j, b) in other.probabilities.iter().enumerate() {
+                probabilities[i + j] += a * b;
+            }
+        }
+        Ok(Distribution {
+            offset,
+            probabilities,
+        })
+    }
+
+    /// Returns the distribution of `op` applied to two independent values.
+    ///
+    /// For each bound of this value, `op` must be smallest and largest at some of `extremes`,
+    /// values between the bounds of `other`, so that the range of the result is known before
+    /// going through every pair of values.
+    fn combine<F>(
+        &self,
+        other: &Distribution,
+        extremes: &[i64],
+        op: F,
+    ) -> Result<Distribution, DiceError>
+    where
+        F: Fn(i64, i64) -> Result<i64, DiceError>,
+    {
+        if self.probabilities.len() * other.probabilities.len() > MAX_WORK {
+            return Err(DiceError::Unsupported(
+                "distribution has too many outcomes to compute",
+            ));
+        }
+        let (mut min, mut max) = (i64::MAX, i64::MIN);
+        for &a in &[self.min(), self.max()] {
+            for &b in extremes {
+                let value = op(a, b)?;
+                min = min.min(value);
+                max = max.max(value);
+            }
+        }
+        if (max as i128 - min as i128) >= MAX_SUPPORT as i128 {
+            return Err(DiceError::Unsupported(
+                "distribution has too many outcomes to compute",
+            ));
+        }
+        let mut probabilities = vec![0.0; (max - min) as usize + 1];
+        for (a, p) in self.iter().filter(|&(_, p)| p > 0.0) {
+            for (b, q) in other.iter().filter(|&(_, q)| q > 0.0) {
+                probabilities[(op(a, b)? - min) as usize] += p * q;
+            }
+        }
+        Distribution::from_outcomes(
+            probabilities
+                .into_iter()
+                .enumerate()
+                .map(|(i, probability)| (min + i as i64, probability)),
+        )
+    }
+
+    /// Returns the distribution of the sum of `count` independent copies of the value.
+    fn repeat(&self, count: u32) -> Result<Distribution, DiceError> {
+        let len = self.probabilities.len();
+        let support = (count as usize).saturating_mul(len - 1) + 1;
+        if (count as usize).saturating_mul(support).saturating_mul(len) / 2 > MAX_WORK {
+            return Err(DiceError::Unsupported(
+                "distribution has too many outcomes to compute",
+            ));
+        }
+        let mut total = Distribution::constant(0);
+        for _ in 0..count {
+            total = total.add(self)?;
+        }
+        Ok(total)
+    }
+
+    /// Returns the distribution of a die rerolled when its face matches the reroll condition,
+    /// once or until it doesn't match anymore.
+    fn reroll(&self, reroll: Reroll) -> Result<Distribution, DiceError> {
+        let rerolled: f64 = self
+            .iter()
+            .filter(|&(face, _)| reroll.condition.matches(face))
+            .map(|(_, probability)| probability)
+            .sum();
+        let weight = reroll_weight(reroll, rerolled)?;
+        Distribution::from_outcomes(
+            self.iter().map(|(face, probability)| {
+                (face, weight(reroll.condition.matches(face), probability))
+            }),
+        )
+    }
+
+    /// Returns the distribution of the dice kept out of `count` independent dice.
+    ///
+    /// Faces are assigned from the best to the worst one: for each face, the number of dice
+    /// showing it is chosen among the dice left, weighted by the multinomial coefficient. The
+    /// first dice assigned are the ones that are kept.
+    fn keep(&self, count: u32, keep: Keep) -> Result<Distribution, DiceError> {
+        let (discarded, lowest_first) = keep.discarded(count as usize);
+        let count = count as usize;
+        let kept = count - discarded;
+        let mut faces: Vec<(i64, f64)> = self.iter().filter(|&(_, p)| p > 0.0).collect();
+        if lowest_first {
+            faces.reverse();
+        }
+        let work = faces
+            .len()
+            .saturating_mul(count)
+            .saturating_mul(count)
+            .saturating_mul(kept + 1);
+        if work > MAX_WORK {
+            return Err(DiceError::Unsupported(
+                "distribution has too many outcomes to compute",
+            ));
+        }
+
+        let binomials = binomials(count);
+        // Probability of each (dice assigned, sum of the kept dice) state.
+        let mut states: BTreeMap<(usize, i64), f64> = BTreeMap::new();
+        states.insert((0, 0), 1.0);
+        for (face, probability) in faces {
+            let mut next = BTreeMap::new();
+            for ((assigned, sum), weight) in states {
+                let left = count - assigned;
+                for (showing, binomial) in binomials[left].iter().enumerate() {
+                    let kept_here = showing.min(kept.saturating_sub(assigned));
+                    let weight = weight * binomial * probability.powi(showing as i32);
+                    *next
+                        .entry((assigned + showing, sum + kept_here as i64 * face))
+                        .or_insert(0.0) += weight;
+                }
+            }
+            states = next;
+        }
+        Distribution::from_outcomes(
+            states
+                .into_iter()
+                .filter(|&((assigned, _), _)| assigned == count)
+                .map(|((_, sum), probability)| (sum, probability)),
+        )
+    }
+}
+
+/// Returns how a die is weighted by a reroll, given the probability `rerolled` that it shows a
+/// face matching the reroll condition: the probability of a face after the reroll, from
+/// whether it matches and its probability before.
+pub(crate) fn reroll_weight(
+    reroll: Reroll,
+    rerolled: f64,
+) -> Result<impl Fn(bool, f64) -> f64, DiceError> {
+    if reroll.recursive && rerolled >= 1.0 {
+        return Err(DiceError::Unsupported("every face of the die is rerolled"));
+    }
+    Ok(move |matches: bool, probability: f64| {
+        let kept = if matches { 0.0 } else { probability };
+        if reroll.recursive {
+            kept / (1.0 - rerolled)
+        } else {
+            kept + rerolled * probability
+        }
+    })
+}
+
+/// Returns Pascal's triangle up to `n`, `binomials[n][k]` being `n` choose `k`.
+fn binomials(n: usize) -> Vec<Vec<f64>> {
+    let mut rows: Vec<Vec<f64>> = Vec::with_capacity(n + 1);
+    for i in 0..=n {
+        let mut row = vec![1.0; i + 1];
+        for k in 1..i {
+            row[k] = rows[i - 1][k - 1] + rows[i - 1][k];
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn distribution(input: &str) -> Distribution {
+        Distribution::of(&parse(input).unwrap()).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn sum_of_dice() {
+        let dist = distribution("2d6");
+        assert_eq!((dist.min(), dist.max()), (2, 12));
+        assert_close(dist.probability(7), 1.0 / 6.0);
+        assert_close(dist.mean(), 7.0);
+        assert_close(dist.variance(), 35.0 / 6.0);
+        assert_eq!(dist.median(), 7);
+    }
+
+    #[test]
+    fn modifiers() {
+        let dist = distribution("2d8+3");
+        assert_close(dist.at_least(15), 15.0 / 64.0);
+        assert_close(dist.at_most(4), 0.0);
+        let dist = distribution("-(1d4*2)");
+        assert_eq!((dist.min(), dist.max()), (-8, -2));
+        assert_close(dist.probability(-3), 0.0);
+        assert_close(dist.probability(-4), 0.25);
+        let dist = distribution("(1d6-4)*(1d3-2)");
+        assert_eq!((dist.min(), dist.max()), (-3, 3));
+        assert_close(dist.probability(0), 8.0 / 18.0);
+        let dist = distribution("1d6/(1d2*2-3)");
+        assert_eq!((dist.min(), dist.max()), (-6, 6));
+        assert_close(dist.probability(0), 0.0);
+        assert_close(dist.probability(-6), 1.0 / 12.0);
+    }
+
+    #[test]
+    fn keep_and_drop() {
+        let dist = distribution("2d20kh1");
+        assert_close(dist.probability(20), 39.0 / 400.0);
+        assert_close(dist.probability(1), 1.0 / 400.0);
+        let dist = distribution("2d20kl1");
+        assert_close(dist.probability(1), 39.0 / 400.0);
+        let dist = distribution("4d6dl1");
+        assert_close(dist.mean(), 15869.0 / 1296.0);
+        assert_eq!(dist, distribution("4d6kh3"));
+    }
+
+    #[test]
+    fn rerolls_and_pools() {
+        assert_close(distribution("1d6r1").mean(), 3.5 + 2.5 / 6.0);
+        assert_close(distribution("1d6rr1").mean(), 4.0);
+        let dist = distribution("2d10>=8f1");
+        assert_close(dist.probability(2), 0.09);
+        assert_close(dist.probability(-2), 0.01);
+    }
+
+    #[test]
+    fn percentiles() {
+        let dist = distribution("1d10");
+        assert_eq!(dist.percentile(0.0), 1);
+        assert_eq!(dist.percentile(0.25), 3);
+        assert_eq!(dist.percentile(1.0), 10);
+    }
+
+    #[test]
+    fn fudge_dice() {
+        let dist = distribution("4dF");
+        assert_eq!((dist.min(), dist.max()), (-4, 4));
+        assert_close(dist.mean(), 0.0);
+        assert_close(dist.probability(4), 1.0 / 81.0);
+        assert_close(distribution("4dF+2").at_least(4), dist.at_least(2));
+        assert_close(distribution("dF.1").probability(0), 4.0 / 6.0);
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(Distribution::of(&parse("3d6!").unwrap()).is_err());
+        assert!(Distribution::of(&parse("1d6/(1d2-1)").unwrap()).is_err());
+        assert!(Distribution::of(&parse("1000000d255").unwrap()).is_err());
+        for input in &["4000000d6kh2000000", "4294967295d6kh1", "1d10000*1d10000"] {
+            assert!(matches!(
+                Distribution::of(&parse(input).unwrap()),
+                Err(DiceError::Unsupported(_))
+            ));
+        }
+        // Products spanning too many values are rejected before going through their outcomes.
+        let start = std::time::Instant::now();
+        assert!(Distribution::of(&parse("1d10000*1d10000").unwrap()).is_err());
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn overflow() {
+        for input in &[
+            "9223372036854775807+1",
+            "9223372036854775807+1d2",
+            "-9223372036854775807-2",
+            "(-9223372036854775807-1)/-1",
+            "-(-9223372036854775807-1)",
+        ] {
+            assert_eq!(
+                Distribution::of(&parse(input).unwrap()),
+                Err(DiceError::Overflow),
+                "{}",
+                input
+            );
+        }
+        let dist = distribution("-9223372036854775807-1");
+        assert_eq!(dist.min(), i64::MIN);
+        assert_close(dist.probability(i64::MAX), 0.0);
+    }
+}
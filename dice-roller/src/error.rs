@@ -0,0 +1,146 @@
+//! Errors returned by the dice roller.
+
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// An error raised while building, parsing or rolling dice.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiceError {
+    /// The input is not a valid dice expression.
+    Malformed(&'static str),
+    /// The parser found a syntax error in a dice expression.
+    Syntax(ParseError),
+    /// The parser found an error in a part of a dice expression, e.g. a term rolling zero dice,
+    /// located by the byte range of that part of the input.
+    Spanned(Box<DiceError>, Range<usize>),
+    /// A die has a number of sides outside of the supported range.
+    SidesOutOfRange(u64),
+    /// A dice term rolls no dice.
+    ZeroDice,
+    /// A set or a term has more dice than allowed.
+    TooManyDice,
+    /// An expression is repeated more times than allowed.
+    TooManyRepeats,
+    /// An expression has a higher complexity score than allowed.
+    TooComplex(u64),
+    /// A number or a total doesn't fit in a signed 64-bit integer.
+    Overflow,
+    /// An expression divides by zero.
+    DivisionByZero,
+    /// An expression references a variable with no value, at a byte offset of the input.
+    UnknownVariable { name: String, position: usize },
+    /// A macro calls itself, directly or through other macros.
+    RecursiveMacro(String),
+    /// An operation isn't supported for the given expression.
+    Unsupported(&'static str),
+}
+
+/// A syntax error located in the input of the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: &'static str,
+    /// What the parser expected instead, if anything in particular.
+    pub expected: Option<&'static str>,
+    /// Byte range of the input the error is about, empty at the end of the input.
+    pub span: Range<usize>,
+}
+
+impl DiceError {
+    /// Returns the byte range of the parsed input the error is about, if it has one.
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            DiceError::Syntax(error) => Some(error.span.clone()),
+            DiceError::Spanned(_, span) => Some(span.clone()),
+            DiceError::UnknownVariable { name, position } => {
+                Some(*position..position + 1 + name.len())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the error without the span it was located by, if the parser gave it one.
+    pub fn unspanned(&self) -> &DiceError {
+        match self {
+            DiceError::Spanned(error, _) => error,
+            error => error,
+        }
+    }
+
+    /// Renders the error for the input it was returned for, with a caret line under the part of
+    /// the input it is about, e.g.
+    ///
+    /// ```text
+    /// 3d6+d
+    ///      ^ right side of separator is not an int, expected number of sides or 'F'
+    /// ```
+    ///
+    /// Errors without a span are rendered as their message alone.
+    pub fn render(&self, input: &str) -> String {
+        let span = match self.span() {
+            Some(span) => span,
+            None => return self.to_string(),
+        };
+        // Spans are byte offsets while carets are lined up by character.
+        let chars = |range: Range<usize>| input.get(range.clone()).map(|s| s.chars().count());
+        let offset = chars(0..span.start).unwrap_or(span.start);
+        let width = chars(span).unwrap_or(1).max(1);
+        let message = match self {
+            DiceError::Syntax(error) => error.to_string(),
+            error => error.unspanned().to_string(),
+        };
+        format!(
+            "{}\n{}{} {}",
+            input,
+            " ".repeat(offset),
+            "^".repeat(width),
+            message
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(expected) = self.expected {
+            write!(f, ", expected {}", expected)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiceError::Malformed(reason) => write!(f, "Argument malformed, {}", reason),
+            DiceError::Syntax(error) => write!(
+                f,
+                "Argument malformed at position {}, {}",
+                error.span.start, error
+            ),
+            DiceError::Spanned(error, _) => error.fmt(f),
+            DiceError::SidesOutOfRange(sides) => {
+                write!(f, "A die can't have {} sides", sides)
+            }
+            DiceError::ZeroDice => write!(f, "At least one die must be rolled"),
+            DiceError::TooManyDice => write!(f, "Maximum amount of dice reached"),
+            DiceError::TooManyRepeats => write!(f, "Maximum amount of repeated rolls reached"),
+            DiceError::TooComplex(complexity) => {
+                write!(
+                    f,
+                    "Expression too complex to roll, complexity {}",
+                    complexity
+                )
+            }
+            DiceError::Overflow => write!(f, "Arithmetic overflow"),
+            DiceError::DivisionByZero => write!(f, "Division by zero"),
+            DiceError::UnknownVariable { name, position } => {
+                write!(f, "Unknown variable @{} at position {}", name, position)
+            }
+            DiceError::RecursiveMacro(name) => write!(f, "Macro {} calls itself", name),
+            DiceError::Unsupported(reason) => write!(f, "Unsupported, {}", reason),
+        }
+    }
+}
+
+impl Error for DiceError {}
@@ -0,0 +1,718 @@
+//! Abstract syntax tree of dice expressions.
+
+use crate::error::DiceError;
+use crate::result::{RollResult, StatementResult};
+use crate::roller::Roller;
+use rand::Rng;
+use std::fmt;
+
+/// A binary arithmetic operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// A comparison operator used by modifier conditions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+/// A condition on the face of a die, e.g. `>=9`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition {
+    pub comparison: Comparison,
+    pub value: i64,
+}
+
+/// Selects which dice of a term count towards its total.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keep {
+    /// Keeps the N highest dice (`kh`).
+    Highest(u32),
+    /// Keeps the N lowest dice (`kl`).
+    Lowest(u32),
+    /// Discards the N highest dice (`dh`).
+    DropHighest(u32),
+    /// Discards the N lowest dice (`dl`).
+    DropLowest(u32),
+}
+
+/// How an exploding die adds its extra rolls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExplodeKind {
+    /// Each extra roll is a new die (`!`).
+    Standard,
+    /// Extra rolls are added to the die that exploded (`!!`).
+    Compound,
+    /// Each extra roll is a new die with one subtracted from it (`!p`).
+    Penetrate,
+}
+
+/// Rolls an extra die whenever a die matches a condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Explode {
+    pub kind: ExplodeKind,
+    /// Faces that trigger an explosion, the highest face if `None`.
+    pub condition: Option<Condition>,
+}
+
+/// Rolls a die again when its face matches a condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reroll {
+    /// Rerolls until the face no longer matches (`rr`) instead of only once (`r` or `ro`).
+    pub recursive: bool,
+    pub condition: Condition,
+}
+
+/// Counts successes instead of summing the faces, e.g. `10d10>=8f1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pool {
+    /// Faces counted as a success.
+    pub success: Condition,
+    /// Faces counted as a failure, subtracted from the successes (`f`).
+    pub failure: Option<Condition>,
+    /// Success faces counted twice (`dbl`).
+    pub double: Option<Condition>,
+}
+
+/// A Fudge die, a six-sided die whose faces read -1, 0 or +1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fudge {
+    /// Two faces each of -1, 0 and +1 (`dF` or `dF.2`).
+    Standard,
+    /// One face each of -1 and +1, the four others being blank (`dF.1`).
+    Single,
+}
+
+/// A group of identical dice rolled together, e.g. `3d6` or `4d6kh3`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiceTerm {
+    pub count: u32,
+    pub sides: u32,
+    /// Reads the faces of the dice as a Fudge die, in which case they have six sides.
+    pub fudge: Option<Fudge>,
+    pub reroll: Option<Reroll>,
+    pub explode: Option<Explode>,
+    pub keep: Option<Keep>,
+    pub pool: Option<Pool>,
+    /// Faces counted as a critical success (`cs`), the highest face if `None`.
+    pub crit_success: Option<Condition>,
+    /// Faces counted as a critical failure (`cf`), the lowest face if `None`.
+    pub crit_failure: Option<Condition>,
+}
+
+/// A dice expression such as `(1d8+2)*2`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// An integer literal.
+    Number(i64),
+    /// A group of dice.
+    Dice(DiceTerm),
+    /// Unary minus.
+    Neg(Box<Expr>),
+    /// A binary operation between two sub-expressions.
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    /// A labelled sub-expression, e.g. `2d6[fire]`.
+    Label(Box<Expr>, String),
+}
+
+/// Order of the entries of a list of rolls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sort {
+    /// Lowest total first (`sort`).
+    Ascending,
+    /// Highest total first (`sort desc`).
+    Descending,
+}
+
+/// Combines the totals of a list of rolls into a single value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    /// Sum of the totals (`sum`).
+    Sum,
+    /// Highest total (`max`).
+    Max,
+    /// Lowest total (`min`).
+    Min,
+    /// Number of totals matching a condition, e.g. `count>=15`.
+    Count(Condition),
+}
+
+/// Rolls an expression several times independently, e.g. `6x 4d6kh3 sort desc`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repeat {
+    pub times: u32,
+    pub expr: Expr,
+    pub sort: Option<Sort>,
+    pub aggregate: Option<Aggregate>,
+}
+
+/// What a statement rolls, either a single expression or a repeated one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    Roll(Expr),
+    Repeat(Repeat),
+}
+
+/// A complete roll request, optionally followed by a comment, e.g.
+/// `1d20+5 # longsword attack`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub comment: Option<String>,
+}
+
+impl From<Expr> for Statement {
+    /// Returns a statement rolling an expression once, without a comment.
+    fn from(expr: Expr) -> Statement {
+        Statement {
+            kind: StatementKind::Roll(expr),
+            comment: None,
+        }
+    }
+}
+
+impl From<Repeat> for Statement {
+    /// Returns a statement rolling a repeated expression, without a comment.
+    fn from(repeat: Repeat) -> Statement {
+        Statement {
+            kind: StatementKind::Repeat(repeat),
+            comment: None,
+        }
+    }
+}
+
+impl Condition {
+    /// Returns true if `value` satisfies the condition.
+    pub fn matches(&self, value: i64) -> bool {
+        match self.comparison {
+            Comparison::Equal => value == self.value,
+            Comparison::Greater => value > self.value,
+            Comparison::GreaterOrEqual => value >= self.value,
+            Comparison::Less => value < self.value,
+            Comparison::LessOrEqual => value <= self.value,
+        }
+    }
+}
+
+impl Explode {
+    /// Returns the condition a die of `term` explodes on, its highest face by default.
+    pub fn trigger(&self, term: &DiceTerm) -> Condition {
+        self.condition.unwrap_or(Condition {
+            comparison: Comparison::Equal,
+            value: term.max_face(),
+        })
+    }
+}
+
+impl Pool {
+    /// Returns true if a die showing `value` counts as a failure.
+    pub fn is_failure(&self, value: i64) -> bool {
+        self.failure.is_some_and(|failure| failure.matches(value))
+    }
+
+    /// Returns what a die showing `value` adds to the net successes.
+    pub fn score(&self, value: i64) -> i64 {
+        let mut score = 0;
+        if self.success.matches(value) {
+            score += match self.double {
+                Some(double) if double.matches(value) => 2,
+                _ => 1,
+            };
+        }
+        if self.is_failure(value) {
+            score -= 1;
+        }
+        score
+    }
+}
+
+impl Keep {
+    /// Returns how many of `count` dice are discarded, and whether the lowest ones go first.
+    pub(crate) fn discarded(self, count: usize) -> (usize, bool) {
+        match self {
+            Keep::Highest(n) => (count.saturating_sub(n as usize), true),
+            Keep::Lowest(n) => (count.saturating_sub(n as usize), false),
+            Keep::DropHighest(n) => ((n as usize).min(count), false),
+            Keep::DropLowest(n) => ((n as usize).min(count), true),
+        }
+    }
+}
+
+impl Fudge {
+    /// Returns the value read on a die that landed on its side `side`, from 1 to 6.
+    pub fn face(self, side: u32) -> i64 {
+        match self {
+            Fudge::Standard => (side as i64 - 1) / 2 - 1,
+            Fudge::Single => match side {
+                1 => -1,
+                6 => 1,
+                _ => 0,
+            },
+        }
+    }
+}
+
+impl DiceTerm {
+    /// Creates a term of `count` dice with `sides` sides and no modifier.
+    pub fn new(count: u32, sides: u32) -> DiceTerm {
+        DiceTerm {
+            count,
+            sides,
+            fudge: None,
+            reroll: None,
+            explode: None,
+            keep: None,
+            pool: None,
+            crit_success: None,
+            crit_failure: None,
+        }
+    }
+
+    /// Creates a term of `count` Fudge dice and no modifier.
+    pub fn fudge(count: u32, fudge: Fudge) -> DiceTerm {
+        DiceTerm {
+            fudge: Some(fudge),
+            ..DiceTerm::new(count, 6)
+        }
+    }
+
+    /// Returns the value read on a die of the term that landed on its side `side`.
+    pub fn face(&self, side: u32) -> i64 {
+        match self.fudge {
+            Some(fudge) => fudge.face(side),
+            None => side as i64,
+        }
+    }
+
+    /// Returns the highest value a die of the term can show.
+    pub fn max_face(&self) -> i64 {
+        self.face(self.sides)
+    }
+
+    /// Returns the lowest value a die of the term can show.
+    pub fn min_face(&self) -> i64 {
+        self.face(1)
+    }
+
+    /// Returns true if a die showing `value` is a critical success, by default when it shows
+    /// the highest face of a die with several faces, other than a Fudge die.
+    pub fn is_crit_success(&self, value: i64) -> bool {
+        match self.crit_success {
+            Some(condition) => condition.matches(value),
+            None => self.has_natural_crits() && value == self.max_face(),
+        }
+    }
+
+    /// Returns true if a die showing `value` is a critical failure, by default when it shows
+    /// the lowest face of a die with several faces, other than a Fudge die.
+    pub fn is_crit_failure(&self, value: i64) -> bool {
+        match self.crit_failure {
+            Some(condition) => condition.matches(value),
+            None => self.has_natural_crits() && value == self.min_face(),
+        }
+    }
+
+    /// Returns true if the highest and lowest faces of a die of the term are critical without a
+    /// threshold. Fudge dice show them too often, so they only count with `cs` and `cf`.
+    fn has_natural_crits(&self) -> bool {
+        self.fudge.is_none() && self.sides > 1
+    }
+
+    /// Returns true if the term has no modifier, so it is a plain sum of dice.
+    pub fn is_plain(&self) -> bool {
+        self.reroll.is_none()
+            && self.explode.is_none()
+            && self.keep.is_none()
+            && self.pool.is_none()
+            && self.crit_success.is_none()
+            && self.crit_failure.is_none()
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression with the default limits, rolling every dice term once.
+    pub fn roll(&self) -> Result<RollResult, DiceError> {
+        Roller::new().roll(self)
+    }
+
+    /// Evaluates the expression with the default limits using the given random number
+    /// generator.
+    pub fn roll_with<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<RollResult, DiceError> {
+        Roller::from_rng(rng).roll(self)
+    }
+
+    /// Returns the binding strength of the expression, used to decide where parentheses go.
+    pub(crate) fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary(BinOp::Add, _, _) | Expr::Binary(BinOp::Sub, _, _) => 1,
+            Expr::Binary(BinOp::Mul, _, _) | Expr::Binary(BinOp::Div, _, _) => 2,
+            Expr::Neg(_) => 3,
+            Expr::Number(_) | Expr::Dice(_) | Expr::Label(_, _) => 4,
+        }
+    }
+
+    /// Returns true if the expression must be in parentheses to be labelled, a label only
+    /// following a number, a dice term or a parenthesized expression.
+    pub(crate) fn is_labelled_in_parentheses(&self) -> bool {
+        self.precedence() < 4 || matches!(self, Expr::Label(..))
+    }
+
+    /// Returns the first operand of a chain of binary operations such as `1+2-3`, with the
+    /// operations applied to it in order, so that long chains are walked in a loop.
+    pub(crate) fn chain(&self) -> (&Expr, Vec<(BinOp, &Expr)>) {
+        let mut first = self;
+        let mut operations = Vec::new();
+        while let Expr::Binary(op, lhs, rhs) = first {
+            operations.push((*op, &**rhs));
+            first = lhs;
+        }
+        operations.reverse();
+        (first, operations)
+    }
+
+    /// Returns the dice terms of the expression if it is a plain sum of dice, like `2d6+1d4`.
+    pub fn dice_terms(&self) -> Option<Vec<&DiceTerm>> {
+        let (first, operations) = self.chain();
+        let mut terms = Vec::with_capacity(operations.len() + 1);
+        for (op, operand) in std::iter::once((BinOp::Add, first)).chain(operations) {
+            match (op, operand) {
+                (BinOp::Add, Expr::Dice(term)) if term.is_plain() => terms.push(term),
+                _ => return None,
+            }
+        }
+        Some(terms)
+    }
+}
+
+impl Aggregate {
+    /// Combines the totals of a list of rolls.
+    pub fn apply(self, totals: &[i64]) -> Result<i64, DiceError> {
+        match self {
+            Aggregate::Sum => totals
+                .iter()
+                .try_fold(0i64, |sum, &total| sum.checked_add(total))
+                .ok_or(DiceError::Overflow),
+            Aggregate::Max => Ok(totals.iter().copied().max().unwrap_or(0)),
+            Aggregate::Min => Ok(totals.iter().copied().min().unwrap_or(0)),
+            Aggregate::Count(condition) => Ok(totals
+                .iter()
+                .filter(|&&total| condition.matches(total))
+                .count() as i64),
+        }
+    }
+}
+
+impl Statement {
+    /// Rolls the statement with the default limits.
+    pub fn roll(&self) -> Result<StatementResult, DiceError> {
+        Roller::new().roll_statement(self)
+    }
+
+    /// Rolls the statement with the default limits using the given random number generator.
+    pub fn roll_with<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<StatementResult, DiceError> {
+        Roller::from_rng(rng).roll_statement(self)
+    }
+}
+
+impl fmt::Display for Condition {
+    /// Writes the condition, omitting `=` for equality as in `r1`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let comparison = match self.comparison {
+            Comparison::Equal => "",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+        };
+        write!(f, "{}{}", comparison, self.value)
+    }
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregate::Sum => write!(f, "sum"),
+            Aggregate::Max => write!(f, "max"),
+            Aggregate::Min => write!(f, "min"),
+            Aggregate::Count(condition) => write!(f, "count{}", condition),
+        }
+    }
+}
+
+impl fmt::Display for Keep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Keep::Highest(n) => write!(f, "kh{}", n),
+            Keep::Lowest(n) => write!(f, "kl{}", n),
+            Keep::DropHighest(n) => write!(f, "dh{}", n),
+            Keep::DropLowest(n) => write!(f, "dl{}", n),
+        }
+    }
+}
+
+impl fmt::Display for Explode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self.kind {
+            ExplodeKind::Standard => "!",
+            ExplodeKind::Compound => "!!",
+            ExplodeKind::Penetrate => "!p",
+        };
+        write!(f, "{}", symbol)?;
+        if let Some(condition) = self.condition {
+            write!(f, "{}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Reroll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = if self.recursive { "rr" } else { "r" };
+        write!(f, "{}{}", symbol, self.condition)
+    }
+}
+
+impl fmt::Display for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // A bare number would be read as part of the number of sides.
+        if self.success.comparison == Comparison::Equal {
+            write!(f, "=")?;
+        }
+        write!(f, "{}", self.success)?;
+        if let Some(failure) = self.failure {
+            write!(f, "f{}", failure)?;
+        }
+        if let Some(double) = self.double {
+            write!(f, "dbl{}", double)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DiceTerm {
+    /// Writes the term in dice notation with its modifiers, e.g. `4d6r1kh3`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fudge {
+            Some(Fudge::Standard) => write!(f, "{}dF", self.count)?,
+            Some(Fudge::Single) => write!(f, "{}dF.1", self.count)?,
+            None => write!(f, "{}d{}", self.count, self.sides)?,
+        }
+        if let Some(reroll) = self.reroll {
+            write!(f, "{}", reroll)?;
+        }
+        // The target of a pool right after an explosion would be read as its condition.
+        let explode_last = self.keep.is_none()
+            && self.pool.is_some()
+            && self
+                .explode
+                .is_some_and(|explode| explode.condition.is_none());
+        if let Some(explode) = self.explode.filter(|_| !explode_last) {
+            write!(f, "{}", explode)?;
+        }
+        if let Some(keep) = self.keep {
+            write!(f, "{}", keep)?;
+        }
+        if let Some(pool) = self.pool {
+            write!(f, "{}", pool)?;
+        }
+        if let Some(explode) = self.explode.filter(|_| explode_last) {
+            write!(f, "{}", explode)?;
+        }
+        if let Some(condition) = self.crit_success {
+            write!(f, "cs{}", condition)?;
+        }
+        if let Some(condition) = self.crit_failure {
+            write!(f, "cf{}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the operand of an operator, in parentheses if needed.
+fn write_operand(f: &mut fmt::Formatter<'_>, operand: &Expr, parenthesize: bool) -> fmt::Result {
+    if parenthesize {
+        write!(f, "(")?;
+        write_expr(f, operand)?;
+        write!(f, ")")
+    } else {
+        write_expr(f, operand)
+    }
+}
+
+/// Writes an expression in canonical notation, labels being written in brackets.
+fn write_expr(f: &mut fmt::Formatter<'_>, expr: &Expr) -> fmt::Result {
+    match expr {
+        Expr::Number(value) => write!(f, "{}", value),
+        Expr::Dice(term) => write!(f, "{}", term),
+        Expr::Neg(operand) => {
+            write!(f, "-")?;
+            write_operand(f, operand, operand.precedence() < 3)
+        }
+        Expr::Label(operand, label) => {
+            write_operand(f, operand, operand.is_labelled_in_parentheses())?;
+            write!(f, "[{}]", label)
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let precedence = expr.precedence();
+            write_operand(f, lhs, lhs.precedence() < precedence)?;
+            let symbol = match op {
+                BinOp::Add => "+",
+                BinOp::Sub => "-",
+                BinOp::Mul => "*",
+                BinOp::Div => "/",
+            };
+            write!(f, "{}", symbol)?;
+            write_operand(f, rhs, rhs.precedence() <= precedence)
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    /// Writes the expression in canonical notation, which parses back to the same expression,
+    /// e.g. `(1d8+2)*2`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_expr(f, self)
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sort::Ascending => write!(f, "sort"),
+            Sort::Descending => write!(f, "sort desc"),
+        }
+    }
+}
+
+impl fmt::Display for Repeat {
+    /// Writes the repeated expression followed by its list operations, e.g.
+    /// `6x 4d6kh3 sort desc sum`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x {}", self.times, self.expr)?;
+        if let Some(sort) = self.sort {
+            write!(f, " {}", sort)?;
+        }
+        if let Some(aggregate) = self.aggregate {
+            write!(f, " {}", aggregate)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            StatementKind::Roll(expr) => write!(f, "{}", expr)?,
+            StatementKind::Repeat(repeat) => write!(f, "{}", repeat)?,
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, " # {}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, parse_statement};
+
+    #[test]
+    fn roll_constant_expression() {
+        let expr = parse("(1+2)*-3").unwrap();
+        assert_eq!(expr.roll().unwrap().total, -9);
+    }
+
+    #[test]
+    fn roll_dice_expression() {
+        let expr = parse("2d6+1d4+3").unwrap();
+        for _ in 0..100 {
+            let result = expr.roll().unwrap();
+            assert!((6..=19).contains(&result.total));
+            assert_eq!(result.terms.len(), 2);
+        }
+    }
+
+    #[test]
+    fn roll_division_by_zero() {
+        let expr = parse("1d6/(2-2)").unwrap();
+        assert_eq!(expr.roll().unwrap_err(), DiceError::DivisionByZero);
+    }
+
+    #[test]
+    fn display_dice_term() {
+        for notation in &[
+            "3d6",
+            "4d6r1kh3",
+            "10d10!>=9>=8f1dbl10",
+            "2d20rr<=2!!kl1",
+            "5d6=6",
+            "4dF",
+            "4dF.1kh2",
+            "1d20cs>=19cf<=2",
+        ] {
+            match parse(notation).unwrap() {
+                Expr::Dice(term) => assert_eq!(&term.to_string(), notation),
+                _ => panic!("{} is not a dice term", notation),
+            }
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for (input, formatted) in &[
+            ("1D6 + 1d6+2 +1", "1d6+1d6+2+1"),
+            ("(1d8 + 2) * -2", "(1d8+2)*-2"),
+            ("--3", "--3"),
+            ("1d6 - (2 - 1)", "1d6-(2-1)"),
+            ("10 / 2 / (5 * 1)", "10/2/(5*1)"),
+            ("-(1d4 + 1)", "-(1d4+1)"),
+            ("3d6>=5!", "3d6>=5!"),
+            ("2d6[fire] + 1d8[ slashing ]", "2d6[fire]+1d8[slashing]"),
+            ("(1d4 + 1)[cold] * 2", "(1d4+1)[cold]*2"),
+            ("((1d6)[a])[b] + 1", "(1d6[a])[b]+1"),
+            ("1d20 + 5 # longsword attack", "1d20+5 # longsword attack"),
+            ("2d6 # a]b", "2d6 # a]b"),
+            ("(2d6[fire]) # big", "2d6[fire] # big"),
+            (
+                "6x 4d6kh3 sort desc count>=15",
+                "6x 4d6kh3 sort desc count>=15",
+            ),
+            ("3X(1d8+2) SUM", "3x 1d8+2 sum"),
+            ("2x (1d6 + 1) # note", "2x 1d6+1 # note"),
+        ] {
+            let statement = parse_statement(input).unwrap();
+            assert_eq!(&statement.to_string(), formatted);
+            assert_eq!(parse_statement(formatted), Ok(statement));
+        }
+
+        // Long chains read back as they were written.
+        let terms: Vec<_> = (1..=300)
+            .map(|i| format!("{}d{}", i % 7 + 1, i % 12 + 2))
+            .collect();
+        let chain = format!("{} * 2 - 3", terms.join(" + "));
+        let expr = parse(&chain).unwrap();
+        assert_eq!(expr.to_string(), chain.replace(' ', ""));
+        assert_eq!(parse(&expr.to_string()), Ok(expr));
+    }
+
+    #[test]
+    fn dice_terms() {
+        let expr = parse("2d6+1d4").unwrap();
+        assert_eq!(expr.dice_terms().unwrap().len(), 2);
+        assert_eq!(parse("2d6+3").unwrap().dice_terms(), None);
+        assert_eq!(parse("4d6kh3").unwrap().dice_terms(), None);
+        assert_eq!(parse("3d6!").unwrap().dice_terms(), None);
+        assert_eq!(parse("2d6r1").unwrap().dice_terms(), None);
+        assert_eq!(parse("5d10>=8").unwrap().dice_terms(), None);
+    }
+}
@@ -0,0 +1,41 @@
+//! The Fate adjective ladder, used to describe the total of Fudge dice.
+
+/// Adjectives of the ladder from -4 to +8.
+const LADDER: [&str; 13] = [
+    "Horrifying",
+    "Catastrophic",
+    "Terrible",
+    "Poor",
+    "Mediocre",
+    "Average",
+    "Fair",
+    "Good",
+    "Great",
+    "Superb",
+    "Fantastic",
+    "Epic",
+    "Legendary",
+];
+
+/// Returns the adjective of the Fate ladder for a value, e.g. "Great" for +4.
+///
+/// Values beyond the ends of the ladder are described by its first or last rung.
+pub fn ladder(value: i64) -> &'static str {
+    let rung = value.clamp(-4, 8) + 4;
+    LADDER[rung as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ladder_rungs() {
+        assert_eq!(ladder(0), "Mediocre");
+        assert_eq!(ladder(2), "Fair");
+        assert_eq!(ladder(4), "Great");
+        assert_eq!(ladder(-2), "Terrible");
+        assert_eq!(ladder(12), "Legendary");
+        assert_eq!(ladder(-9), "Horrifying");
+    }
+}
@@ -0,0 +1,176 @@
+//! Inline rolls written inside free text, such as `I swing [[1d20+4]]`.
+//!
+//! Every `[[...]]` marker is parsed as a statement and rolled, and replaced in the text by its
+//! total. Markers that can't be rolled are left in place, their error being reported with the
+//! other results.
+
+use crate::error::DiceError;
+use crate::parser::{parse_statement_with, Variables};
+use crate::result::StatementResult;
+use crate::roller::Roller;
+use rand::Rng;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+/// An inline roll found in a text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineRoll {
+    /// Byte range of the marker in the original text, brackets included.
+    pub span: Range<usize>,
+    /// The statement between the brackets.
+    pub source: String,
+    /// The result of the roll, the span of a syntax error being relative to `source`.
+    pub result: Result<StatementResult, DiceError>,
+}
+
+/// A text whose inline rolls were rolled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineText {
+    /// The text with every rolled marker replaced by its total.
+    pub text: String,
+    /// Every marker, in the order they appear in the text.
+    pub rolls: Vec<InlineRoll>,
+}
+
+impl InlineText {
+    /// Returns the markers that couldn't be rolled.
+    pub fn errors(&self) -> impl Iterator<Item = &InlineRoll> {
+        self.rolls.iter().filter(|roll| roll.result.is_err())
+    }
+}
+
+/// Rolls every inline roll of a text with the default limits.
+pub fn roll_inline(text: &str) -> InlineText {
+    roll_inline_with(text, &mut Roller::new(), &HashMap::new())
+}
+
+/// Rolls every inline roll of a text with a roller, resolving variables in `variables`.
+pub fn roll_inline_with<R: Rng>(
+    text: &str,
+    roller: &mut Roller<R>,
+    variables: &dyn Variables,
+) -> InlineText {
+    let mut output = String::with_capacity(text.len());
+    let mut rolls = Vec::new();
+    let mut copied = 0;
+    while let Some(open) = text[copied..].find("[[").map(|open| copied + open) {
+        output.push_str(&text[copied..open]);
+        let (end, source, result) = match closing(&text[open + 2..]) {
+            Some(close) => {
+                let source = &text[open + 2..open + 2 + close];
+                let result = parse_statement_with(source, variables)
+                    .and_then(|statement| roller.roll_statement(&statement));
+                (open + 2 + close + 2, source, result)
+            }
+            None => (
+                text.len(),
+                &text[open + 2..],
+                Err(DiceError::Malformed("missing closing inline roll marker")),
+            ),
+        };
+        match &result {
+            Ok(result) => output.push_str(&summary(result)),
+            Err(error) => {
+                log::info!("Leaving inline roll {} in place: {}", source, error);
+                output.push_str(&text[open..end]);
+            }
+        }
+        rolls.push(InlineRoll {
+            span: open..end,
+            source: source.to_string(),
+            result,
+        });
+        copied = end;
+    }
+    output.push_str(&text[copied..]);
+    InlineText {
+        text: output,
+        rolls,
+    }
+}
+
+/// Returns the offset of the `]]` closing an inline roll in `input`, which starts right after
+/// the opening marker, skipping the brackets of labels.
+fn closing(input: &str) -> Option<usize> {
+    let bytes = input.as_bytes();
+    let mut depth = 0;
+    for (i, &c) in bytes.iter().enumerate() {
+        match c {
+            b'[' => depth += 1,
+            b']' if depth > 0 => depth -= 1,
+            b']' if bytes.get(i + 1) == Some(&b']') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the text replacing an inline roll: its total, or the totals of a list of rolls
+/// without an aggregate.
+fn summary(result: &StatementResult) -> String {
+    match (result.total(), result) {
+        (Some(total), _) => total.to_string(),
+        (None, StatementResult::Repeat(list)) => {
+            let totals: Vec<String> = list.totals().iter().map(i64::to_string).collect();
+            totals.join(", ")
+        }
+        (None, StatementResult::Roll(result)) => result.total.to_string(),
+    }
+}
+
+impl fmt::Display for InlineText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_rolls() {
+        let inline = roll_inline("I swing [[1d20+4]] and deal [[2d6[slashing] + 2]] damage");
+        assert_eq!(inline.rolls.len(), 2);
+        let totals: Vec<i64> = inline
+            .rolls
+            .iter()
+            .map(|roll| roll.result.as_ref().unwrap().total().unwrap())
+            .collect();
+        assert_eq!(
+            inline.text,
+            format!("I swing {} and deal {} damage", totals[0], totals[1])
+        );
+        assert_eq!(inline.rolls[0].span, 8..18);
+        assert_eq!(inline.rolls[1].source, "2d6[slashing] + 2");
+        assert_eq!(inline.errors().count(), 0);
+
+        let mut roller = Roller::seeded(42);
+        let mut variables = HashMap::new();
+        variables.insert("str".to_string(), "3".to_string());
+        let inline = roll_inline_with("[[3x 1d1+@str]]", &mut roller, &variables);
+        assert_eq!(inline.text, "4, 4, 4");
+    }
+
+    #[test]
+    fn inline_malformed_markers() {
+        let inline = roll_inline("Oops [[2dd8]] and [[1d1]] then [[1d6");
+        assert_eq!(inline.text, "Oops [[2dd8]] and 1 then [[1d6");
+        let errors: Vec<&DiceError> = inline
+            .errors()
+            .map(|roll| roll.result.as_ref().unwrap_err())
+            .collect();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].render(&inline.rolls[0].source),
+            "2dd8\n  ^ right side of separator is not an int, expected number of sides or 'F'"
+        );
+        assert_eq!(
+            errors[1],
+            &DiceError::Malformed("missing closing inline roll marker")
+        );
+        assert_eq!(inline.rolls[2].span, 31..36);
+        assert_eq!(roll_inline("no rolls [here]").text, "no rolls [here]");
+    }
+}
@@ -1,100 +1,386 @@
 use rand::distributions::Uniform;
 use rand::Rng;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
 
-/// This magic number comes from the following computation:
-/// * A die has a maximum of 255 sides.
-/// * The biggest dice roll is 2^32 - 1 (roll_result is u32) = 4294967295.
-/// * The worst case scenario is that all the dice rolled give their max value = 255.
-/// -> The max number of dice we can roll is 4294967295 / 255 = 16843009
-const MAX_NUMBER_DICE: usize = std::u32::MAX as usize / std::u8::MAX as usize;
+pub mod analysis;
+pub mod custom;
+pub mod distribution;
+pub mod error;
+pub mod expr;
+pub mod fate;
+pub mod inline;
+pub mod macros;
+pub mod normalize;
+pub mod parser;
+pub mod percentile;
+pub mod result;
+pub mod roller;
+pub mod simulation;
+
+pub use analysis::Analysis;
+pub use custom::{Cancel, CustomDie, CustomDieResult, CustomPool, Face, PoolResult};
+pub use distribution::Distribution;
+pub use error::{DiceError, ParseError};
+pub use expr::{
+    Aggregate, BinOp, Comparison, Condition, DiceTerm, Explode, ExplodeKind, Expr, Fudge, Keep,
+    Pool, Repeat, Reroll, Sort, Statement, StatementKind,
+};
+pub use inline::{roll_inline, roll_inline_with, InlineRoll, InlineText};
+pub use macros::{Macro, Macros};
+pub use parser::{parse, parse_statement, parse_statement_with, parse_with, Variables};
+pub use percentile::{Grade, Percentile, PercentileResult};
+pub use result::{DieResult, ListResult, RollResult, StatementResult, TermResult};
+pub use roller::{Limits, Roller};
+pub use simulation::{Histogram, Simulation};
 
 /// A single die characterized by its number of sides.
-///
-/// The maximum number of sides is 255.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Die {
-    pub number_sides: u8,
+    pub number_sides: u32,
 }
 
 impl Die {
     /// Rolls a die once.
-    pub fn roll_die(&self) -> u8 {
-        let rng = rand::thread_rng();
+    pub fn roll_die(&self) -> u32 {
+        self.roll_with(&mut rand::thread_rng())
+    }
+
+    /// Rolls a die once using the given random number generator.
+    pub fn roll_with<R: Rng + ?Sized>(&self, rng: &mut R) -> u32 {
         let die_range = Uniform::new_inclusive(1, self.number_sides);
-        let mut roll_die = rng.sample_iter(&die_range);
-        roll_die.next().unwrap()
+        rng.sample(die_range)
+    }
+
+    /// Returns a set of `count` such dice, bounded by the default limits.
+    pub fn checked_mul(self, count: u32) -> Result<Dice, DiceError> {
+        let mut dice = Dice::default();
+        if count > 0 {
+            dice.add_group(self.number_sides, count as u64)?;
+        }
+        Ok(dice)
+    }
+}
+
+impl FromStr for Die {
+    type Err = DiceError;
+
+    /// Parses a single die such as `d20` or `1d20`.
+    fn from_str(s: &str) -> Result<Die, DiceError> {
+        match parser::parse(s)? {
+            Expr::Dice(term) if term.count == 1 && term.is_plain() && term.fudge.is_none() => {
+                Ok(Die {
+                    number_sides: term.sides,
+                })
+            }
+            _ => Err(DiceError::Malformed("not a single die")),
+        }
+    }
+}
+
+impl fmt::Display for Die {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "1d{}", self.number_sides)
+    }
+}
+
+impl Mul<u32> for Die {
+    type Output = Dice;
+
+    /// Returns a set of `count` such dice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set exceeds the default limits, see [`Die::checked_mul`].
+    fn mul(self, count: u32) -> Dice {
+        self.checked_mul(count)
+            .expect("set of dice exceeding the limits")
     }
 }
 
 /// A set of dice.
 ///
-/// There is maximum amount of dice a set can contain is 16843009 (MAX_NUMBER_DICE constant).
-#[derive(Default, PartialEq)]
+/// Dice are stored as groups of consecutive dice with the same number of sides, so a set of
+/// millions of dice only takes a few bytes. The amount of dice a set can contain and their number
+/// of sides are bounded by its limits. A constant modifier can be added to the sum of the dice.
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Dice {
-    pub dice: Vec<Die>,
+    groups: Vec<(u32, u64)>,
+    modifier: i64,
+    pub limits: Limits,
 }
 
 impl Dice {
+    /// Creates an empty set of dice bounded by the given limits.
+    pub fn with_limits(limits: Limits) -> Dice {
+        Dice {
+            groups: Vec::new(),
+            modifier: 0,
+            limits,
+        }
+    }
+
+    /// Returns the number of dice in the set.
+    pub fn len(&self) -> u64 {
+        self.groups.iter().map(|&(_, count)| count).sum()
+    }
+
+    /// Returns true if the set has no dice.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Returns the dice of the set as `(sides, count)` groups, in the order they were added.
+    pub fn groups(&self) -> &[(u32, u64)] {
+        &self.groups
+    }
+
+    /// Returns the constant added to the sum of the dice.
+    pub fn modifier(&self) -> i64 {
+        self.modifier
+    }
+
+    /// Returns every die of the set, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = Die> + '_ {
+        self.groups.iter().flat_map(|&(sides, count)| {
+            (0..count).map(move |_| Die {
+                number_sides: sides,
+            })
+        })
+    }
+
     /// Adds a die to the current set of dice.
-    pub fn add_die(&mut self, die: Die) -> Result<(), &'static str> {
-        if self.dice.len() < MAX_NUMBER_DICE {
-            log::info!("Adding 1d{}", &die.number_sides);
-            self.dice.push(die);
-        } else {
-            return Err("Maximum amount of dice reached");
+    pub fn add_die(&mut self, die: Die) -> Result<(), DiceError> {
+        log::info!("Adding 1d{}", &die.number_sides);
+        self.add_group(die.number_sides, 1)
+    }
+
+    /// Adds multiple dice to the dice set.
+    ///
+    /// Each argument is parsed as a set of dice like `2d6+1d4+1`, as by [`Dice::from_str`] but
+    /// bounded by the limits of this set.
+    pub fn add_dice(&mut self, args: &[&str]) -> Result<(), DiceError> {
+        for arg in args {
+            let dice = Dice::parse_with_limits(arg, self.limits.clone())?;
+            for &(sides, count) in &dice.groups {
+                log::info!("Adding {}d{} to set", count, sides);
+                self.add_group(sides, count)?;
+            }
+            self.add_modifier(dice.modifier)?;
         }
         Ok(())
     }
 
-    /// Parses argument and returns a tuple with the number of dice of a specific type.
-    fn parse_add_args(arg: &str) -> Result<(u8, u8), &'static str> {
-        let parsed_arg: Vec<&str> = arg.split(|c| c == 'd' || c == 'D').collect();
-        if parsed_arg.len() != 2 {
-            return Err("Argument malformed, too many characters");
+    /// Parses a set of dice such as `3d6` or `2d6+1d4+1`, bounded by the given limits.
+    fn parse_with_limits(s: &str, limits: Limits) -> Result<Dice, DiceError> {
+        let mut dice = Dice::with_limits(limits);
+        dice.add_sum(&parser::parse(s)?)?;
+        Ok(dice)
+    }
+
+    /// Adds `count` dice with the given number of sides, either all of them or none.
+    fn add_group(&mut self, sides: u32, count: u64) -> Result<(), DiceError> {
+        self.limits.check_sides(sides)?;
+        match self.len().checked_add(count) {
+            Some(len) if len <= self.limits.max_dice => {}
+            _ => return Err(DiceError::TooManyDice),
+        }
+        match self.groups.last_mut() {
+            Some((last, total)) if *last == sides => *total += count,
+            _ => self.groups.push((sides, count)),
         }
-        let number_dice: u8 = parsed_arg[0]
-            .parse()
-            .expect("Argument malformed, left side of separator is not an int");
-        let number_sides: u8 = parsed_arg[1]
-            .parse()
-            .expect("Argument malformed, right side of separator is not an int");
-        Ok((number_dice, number_sides))
+        Ok(())
     }
 
-    /// Adds multiple dice to the dice set.
-    pub fn add_dice(&mut self, args: &[&str]) -> Result<(), &'static str> {
-        for arg in args {
-            let dice_args = Dice::parse_add_args(arg)?;
-            log::info!("Adding {}d{} to set", dice_args.0, dice_args.1);
-            for _ in 0..dice_args.0 {
-                self.add_die(Die {
-                    number_sides: dice_args.1,
-                })?;
+    /// Adds the dice and constants of a sum such as `2d6+1d4-1` to the set.
+    fn add_sum(&mut self, expr: &Expr) -> Result<(), DiceError> {
+        match expr {
+            Expr::Binary(BinOp::Add, lhs, rhs) => {
+                self.add_sum(lhs)?;
+                self.add_sum(rhs)
+            }
+            Expr::Binary(BinOp::Sub, lhs, rhs) => match **rhs {
+                Expr::Number(value) => {
+                    self.add_sum(lhs)?;
+                    self.add_modifier(value.checked_neg().ok_or(DiceError::Overflow)?)
+                }
+                _ => Err(DiceError::Malformed("not a plain set of dice")),
+            },
+            Expr::Neg(expr) => match **expr {
+                Expr::Number(value) => {
+                    self.add_modifier(value.checked_neg().ok_or(DiceError::Overflow)?)
+                }
+                _ => Err(DiceError::Malformed("not a plain set of dice")),
+            },
+            Expr::Number(value) => self.add_modifier(*value),
+            Expr::Dice(term) if term.is_plain() && term.fudge.is_none() => {
+                self.add_group(term.sides, term.count as u64)
             }
+            _ => Err(DiceError::Malformed("not a plain set of dice")),
         }
+    }
+
+    /// Adds a constant to the sum of the dice.
+    ///
+    /// The modifier can't be `i64::MIN`, whose opposite doesn't fit in an `i64`, so that the set
+    /// is always written as an expression that parses back.
+    fn add_modifier(&mut self, value: i64) -> Result<(), DiceError> {
+        self.modifier = self
+            .modifier
+            .checked_add(value)
+            .filter(|&modifier| modifier != i64::MIN)
+            .ok_or(DiceError::Overflow)?;
         Ok(())
     }
 
-    /// Rolls all dice in the set and returns the sum of the rolls.
-    pub fn roll_dice(&mut self) -> u32 {
-        let mut roll_result: u32 = 0;
-        for die in &self.dice {
-            roll_result += die.roll_die() as u32;
+    /// Returns the union of two sets, bounded by the limits of the first one.
+    pub fn checked_add(mut self, other: Dice) -> Result<Dice, DiceError> {
+        for &(sides, count) in &other.groups {
+            self.add_group(sides, count)?;
+        }
+        self.add_modifier(other.modifier)?;
+        Ok(self)
+    }
+
+    /// Returns the set with a constant added to the sum of its dice.
+    pub fn checked_add_modifier(mut self, value: i64) -> Result<Dice, DiceError> {
+        self.add_modifier(value)?;
+        Ok(self)
+    }
+
+    /// Returns the set as an expression summing its dice, each group of dice being a term, and
+    /// its modifier.
+    pub fn to_expr(&self) -> Expr {
+        let dice = self
+            .groups
+            .iter()
+            .flat_map(|&(sides, count)| {
+                // Split groups too big for a single term.
+                let full = count / u32::MAX as u64;
+                let rest = (count % u32::MAX as u64) as u32;
+                (0..full)
+                    .map(move |_| DiceTerm::new(u32::MAX, sides))
+                    .chain((rest > 0).then(|| DiceTerm::new(rest, sides)))
+            })
+            .map(Expr::Dice)
+            .reduce(|lhs, rhs| Expr::Binary(BinOp::Add, Box::new(lhs), Box::new(rhs)));
+        match (dice, self.modifier) {
+            (Some(dice), 0) => dice,
+            (Some(dice), modifier) if modifier < 0 => Expr::Binary(
+                BinOp::Sub,
+                Box::new(dice),
+                Box::new(Expr::Number(-modifier)),
+            ),
+            (Some(dice), modifier) => {
+                Expr::Binary(BinOp::Add, Box::new(dice), Box::new(Expr::Number(modifier)))
+            }
+            (None, modifier) => Expr::Number(modifier),
+        }
+    }
+
+    /// Rolls all dice in the set and returns every roll along with their sum.
+    pub fn roll_dice(&mut self) -> Result<RollResult, DiceError> {
+        self.roll_with(&mut rand::thread_rng())
+    }
+
+    /// Rolls all dice in the set using the given random number generator.
+    pub fn roll_with<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<RollResult, DiceError> {
+        let mut roller = Roller::from_rng(rng);
+        roller.limits = self.limits.clone();
+        roller.roll(&self.to_expr())
+    }
+
+    /// Rolls all dice in the set and returns only their sum, modifier included.
+    ///
+    /// Unlike [`Dice::roll_dice`], no result is kept for individual dice, which makes it suited
+    /// to huge sets.
+    pub fn sum(&self) -> Result<i64, DiceError> {
+        self.sum_with(&mut rand::thread_rng())
+    }
+
+    /// Rolls all dice in the set using the given random number generator and returns only their
+    /// sum.
+    pub fn sum_with<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<i64, DiceError> {
+        let mut total = self.modifier;
+        for &(sides, count) in &self.groups {
+            let die_range = Uniform::new_inclusive(1, sides as u64);
+            let group = rng
+                .sample_iter(die_range)
+                .take(count as usize)
+                .try_fold(0u64, |total, face| total.checked_add(face))
+                .and_then(|group| i64::try_from(group).ok())
+                .ok_or(DiceError::Overflow)?;
+            total = total.checked_add(group).ok_or(DiceError::Overflow)?;
         }
-        roll_result
+        Ok(total)
+    }
+}
+
+impl FromStr for Dice {
+    type Err = DiceError;
+
+    /// Parses a set of dice such as `3d6` or `2d6+1d4+1`, bounded by the default limits.
+    fn from_str(s: &str) -> Result<Dice, DiceError> {
+        Dice::parse_with_limits(s, Limits::default())
+    }
+}
+
+/// Writes the set in `NdM` notation, such as `2d6+1d4+1`.
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_expr())
+    }
+}
+
+impl Add for Dice {
+    type Output = Dice;
+
+    /// Returns the union of two sets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the union exceeds the limits of the first set, see [`Dice::checked_add`].
+    fn add(self, other: Dice) -> Dice {
+        self.checked_add(other)
+            .expect("set of dice exceeding the limits")
+    }
+}
+
+impl Add<i64> for Dice {
+    type Output = Dice;
+
+    /// Returns the set with a constant added to the sum of its dice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the modifier overflows, see [`Dice::checked_add_modifier`].
+    fn add(self, value: i64) -> Dice {
+        self.checked_add_modifier(value)
+            .expect("modifier overflowing")
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn syntax_error(result: Result<(), DiceError>) -> &'static str {
+        match result {
+            Err(DiceError::Syntax(error)) => error.message,
+            result => panic!("{:?} is not a syntax error", result),
+        }
+    }
 
     #[test]
     fn add_die() {
         let die = Die { number_sides: 6 };
         let mut dice: Dice = Default::default();
         dice.add_die(die).unwrap();
-        assert_eq!(dice.dice[0], Die { number_sides: 6 });
+        assert_eq!(dice.iter().next(), Some(Die { number_sides: 6 }));
     }
 
     #[test]
@@ -102,35 +388,221 @@ mod tests {
         let args = ["1d6", "2D3", "3d100"];
         let mut dice: Dice = Default::default();
         dice.add_dice(&args).unwrap();
-        assert_eq!(dice.dice[0], Die { number_sides: 6 });
-        assert_eq!(dice.dice[1], Die { number_sides: 3 });
-        assert_eq!(dice.dice[2], Die { number_sides: 3 });
-        assert_eq!(dice.dice[3], Die { number_sides: 100 });
-        assert_eq!(dice.dice[4], Die { number_sides: 100 });
-        assert_eq!(dice.dice[5], Die { number_sides: 100 });
+        let dice: Vec<Die> = dice.iter().collect();
+        assert_eq!(dice[0], Die { number_sides: 6 });
+        assert_eq!(dice[1], Die { number_sides: 3 });
+        assert_eq!(dice[2], Die { number_sides: 3 });
+        assert_eq!(dice[3], Die { number_sides: 100 });
+        assert_eq!(dice[4], Die { number_sides: 100 });
+        assert_eq!(dice[5], Die { number_sides: 100 });
+    }
+
+    #[test]
+    fn add_dice_expression() {
+        let args = ["2d6 + d4"];
+        let mut dice: Dice = Default::default();
+        dice.add_dice(&args).unwrap();
+        assert_eq!(dice.len(), 3);
+        assert_eq!(dice.iter().nth(2), Some(Die { number_sides: 4 }));
+    }
+
+    #[test]
+    fn add_dice_with_modifier() {
+        let with_modifier = ["1d6+2", "1d4 - 3"];
+        let mut dice: Dice = Default::default();
+        dice.add_dice(&with_modifier).unwrap();
+        assert_eq!(dice.groups(), &[(6, 1), (4, 1)]);
+        assert_eq!(dice.modifier(), -1);
+        assert_eq!(dice, "1d6+2+1d4-3".parse().unwrap());
+        assert_eq!(
+            dice.add_dice(&["4d6kh3"]),
+            Err(DiceError::Malformed("not a plain set of dice"))
+        );
+    }
+
+    #[test]
+    fn roll_dice() {
+        let args = ["2d6", "1d4"];
+        let mut dice: Dice = Default::default();
+        dice.add_dice(&args).unwrap();
+        let result = dice.roll_dice().unwrap();
+        assert_eq!(result.terms.len(), 2);
+        assert_eq!(result.terms[0].dice.len(), 2);
+        assert!((3..=16).contains(&result.total));
+        assert_eq!(Dice::default().roll_dice().unwrap().total, 0);
+    }
+
+    #[test]
+    fn roll_with_seed() {
+        let die = Die { number_sides: 20 };
+        let mut rng = StdRng::seed_from_u64(42);
+        let first: Vec<u32> = (0..10).map(|_| die.roll_with(&mut rng)).collect();
+        let mut rng = StdRng::seed_from_u64(42);
+        let second: Vec<u32> = (0..10).map(|_| die.roll_with(&mut rng)).collect();
+        assert_eq!(first, second);
+
+        let mut dice: Dice = Default::default();
+        dice.add_dice(&["3d6", "2d8"]).unwrap();
+        let first = dice.roll_with(&mut StdRng::seed_from_u64(7));
+        let second = dice.roll_with(&mut StdRng::seed_from_u64(7));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn add_die_without_sides() {
+        let mut dice: Dice = Default::default();
+        assert_eq!(
+            dice.add_die(Die { number_sides: 0 }),
+            Err(DiceError::SidesOutOfRange(0))
+        );
+        assert!(dice.is_empty());
+    }
+
+    #[test]
+    fn add_die_within_limits() {
+        let mut dice = Dice::with_limits(Limits {
+            max_dice: 2,
+            max_sides: 1000,
+            ..Default::default()
+        });
+        dice.add_die(Die { number_sides: 1000 }).unwrap();
+        assert_eq!(
+            dice.add_die(Die { number_sides: 1001 }),
+            Err(DiceError::SidesOutOfRange(1001))
+        );
+        dice.add_dice(&["1d20"]).unwrap();
+        assert_eq!(dice.add_dice(&["1d20"]), Err(DiceError::TooManyDice));
+        assert_eq!(dice.len(), 2);
+    }
+
+    #[test]
+    fn roll_large_dice() {
+        let mut dice = Dice::with_limits(Limits {
+            max_sides: u32::MAX,
+            ..Default::default()
+        });
+        dice.add_dice(&["3d4294967295"]).unwrap();
+        let result = dice.roll_dice().unwrap();
+        assert!((3..=3 * u32::MAX as i64).contains(&result.total));
+    }
+
+    #[test]
+    fn groups_dice() {
+        let mut dice: Dice = Default::default();
+        dice.add_dice(&["2d6", "1d6+1d8", "16843000d6"]).unwrap();
+        dice.add_die(Die { number_sides: 6 }).unwrap();
+        assert_eq!(dice.groups(), &[(6, 3), (8, 1), (6, 16843001)]);
+        assert_eq!(dice.len(), 16843005);
+        assert_eq!(
+            dice.add_dice(&["5d4"]),
+            Err(DiceError::TooManyDice),
+            "a group is added either whole or not at all"
+        );
+        assert_eq!(dice.len(), 16843005);
+    }
+
+    #[test]
+    fn sum_huge_set() {
+        let mut dice: Dice = Default::default();
+        dice.add_dice(&["1000000d6"]).unwrap();
+        let total = dice.sum_with(&mut StdRng::seed_from_u64(42)).unwrap();
+        let mean = 1000000.0 * 3.5;
+        assert!((total as f64 - mean).abs() < mean * 0.01);
+        assert_eq!(Dice::default().sum().unwrap(), 0);
     }
 
     #[test]
-    #[should_panic(expected = "Argument malformed, left side of separator is not an int")]
     fn add_dice_not_int_first_argument() {
         let not_first_int = ["ad6"];
         let mut dice: Dice = Default::default();
-        dice.add_dice(&not_first_int).unwrap();
+        assert_eq!(
+            syntax_error(dice.add_dice(&not_first_int)),
+            "unexpected character"
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Argument malformed, right side of separator is not an int")]
     fn add_dice_not_int_second_argument() {
         let not_second_int = ["1da"];
         let mut dice: Dice = Default::default();
-        dice.add_dice(&not_second_int).unwrap();
+        assert_eq!(
+            syntax_error(dice.add_dice(&not_second_int)),
+            "right side of separator is not an int"
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Argument malformed, too many characters")]
     fn add_dice_too_many_arguments() {
         let too_many_args = ["1d6d"];
         let mut dice: Dice = Default::default();
-        dice.add_dice(&too_many_args).unwrap();
+        assert_eq!(
+            syntax_error(dice.add_dice(&too_many_args)),
+            "missing direction after keep or drop"
+        );
+    }
+
+    #[test]
+    fn add_dice_zero_dice() {
+        let zero_dice = ["0d6"];
+        let mut dice: Dice = Default::default();
+        let error = dice.add_dice(&zero_dice).unwrap_err();
+        assert_eq!(error.unspanned(), &DiceError::ZeroDice);
+        assert_eq!(
+            DiceError::ZeroDice.to_string(),
+            "At least one die must be rolled"
+        );
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let d20: Die = "d20".parse().unwrap();
+        assert_eq!(d20, Die { number_sides: 20 });
+        assert_eq!(d20.to_string(), "1d20");
+        assert_eq!(
+            "2d20".parse::<Die>(),
+            Err(DiceError::Malformed("not a single die"))
+        );
+
+        let dice: Dice = "3d6".parse().unwrap();
+        assert_eq!(dice.groups(), &[(6, 3)]);
+        assert_eq!(dice.to_string(), "3d6");
+        let dice: Dice = "2d6 + 1d4 - 2 + 1".parse().unwrap();
+        assert_eq!(dice.modifier(), -1);
+        assert_eq!(dice.to_string(), "2d6+1d4-1");
+        assert_eq!(dice.to_string().parse(), Ok(dice));
+        assert_eq!(Dice::default().to_string(), "0");
+        let dice = (0..300).fold(Dice::default(), |dice, i| {
+            dice + Die {
+                number_sides: 6 + i % 2 * 2,
+            } * 1
+        });
+        assert_eq!(dice.groups().len(), 300);
+        assert_eq!(dice.to_string().parse(), Ok(dice));
+        assert_eq!(
+            "4d6kh3".parse::<Dice>(),
+            Err(DiceError::Malformed("not a plain set of dice"))
+        );
+    }
+
+    #[test]
+    fn dice_operators() {
+        let d6 = Die { number_sides: 6 };
+        let d8 = Die { number_sides: 8 };
+        let dice = d6 * 2 + d8 * 1 + d6 * 1 + 3;
+        assert_eq!(dice.groups(), &[(6, 2), (8, 1), (6, 1)]);
+        assert_eq!(dice.to_string(), "2d6+1d8+1d6+3");
+        assert!((7..=25).contains(&dice.sum().unwrap()));
+        assert_eq!((dice + -5).modifier(), -2);
+        assert!((d6 * 0).is_empty());
+        assert_eq!(d6.checked_mul(u32::MAX), Err(DiceError::TooManyDice));
+
+        let dice = (d6 * 1).checked_add_modifier(-i64::MAX).unwrap();
+        assert_eq!(dice.to_string(), "1d6-9223372036854775807");
+        assert_eq!(dice.to_string().parse(), Ok(dice.clone()));
+        assert_eq!(dice.checked_add_modifier(-1), Err(DiceError::Overflow));
+        assert_eq!(
+            Dice::default().checked_add_modifier(i64::MIN),
+            Err(DiceError::Overflow)
+        );
     }
 }
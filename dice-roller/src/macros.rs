@@ -0,0 +1,406 @@
+//! User-defined macros expanding to dice expressions.
+//!
+//! A macro has a name, parameters and a body in which `{param}` is replaced by the argument of
+//! the call, so with `attack(bonus) = 1d20+{bonus}` the input `attack(5) + 1` expands to
+//! `(1d20+5) + 1`. Bodies and arguments can call other macros, expansion failing on macros that
+//! call themselves, nest too deeply or expand to too long an input.
+
+use crate::error::DiceError;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Default maximum number of nested macro calls.
+const MAX_DEPTH: usize = 16;
+
+/// Default maximum number of bytes written while expanding an input.
+const MAX_LENGTH: usize = 100_000;
+
+/// A macro definition such as `attack(bonus) = 1d20+{bonus}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: String,
+}
+
+/// A set of macros, keyed by name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macros {
+    macros: BTreeMap<String, Macro>,
+    /// Maximum number of nested macro calls while expanding an input, calls in arguments
+    /// included.
+    pub max_depth: usize,
+    /// Maximum number of bytes written while expanding an input, expanded arguments included.
+    pub max_length: usize,
+}
+
+/// State of the expansion of an input.
+#[derive(Default)]
+struct Expansion {
+    /// Macros whose bodies are being expanded.
+    calls: Vec<String>,
+    /// Number of calls being expanded, whether in bodies or in arguments.
+    depth: usize,
+    /// Number of bytes written so far, at every level of nesting.
+    length: usize,
+}
+
+/// Returns true if `name` is a valid macro or parameter name, a letter or an underscore followed
+/// by letters, digits and underscores.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Macro {
+    /// Creates a macro, checking its names and that its body only uses its parameters.
+    pub fn new(name: &str, params: &[&str], body: &str) -> Result<Macro, DiceError> {
+        if !is_identifier(name) {
+            return Err(DiceError::Malformed("invalid macro name"));
+        }
+        if !params.iter().all(|param| is_identifier(param)) {
+            return Err(DiceError::Malformed("invalid macro parameter"));
+        }
+        let mut rest = body;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or(DiceError::Malformed("missing closing brace in macro body"))?;
+            if !params.contains(&&rest[start + 1..start + end]) {
+                return Err(DiceError::Malformed(
+                    "macro body references an unknown parameter",
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        Ok(Macro {
+            name: name.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            body: body.trim().to_string(),
+        })
+    }
+
+    /// Parses a definition written `name(param, ...) = body`, the parentheses being optional
+    /// for macros without parameters.
+    pub fn parse(definition: &str) -> Result<Macro, DiceError> {
+        let (head, body) = definition
+            .split_once('=')
+            .ok_or(DiceError::Malformed("expected '=' in macro definition"))?;
+        let head = head.trim();
+        let (name, params) = match head.find('(') {
+            Some(open) => {
+                let params = head[open + 1..]
+                    .strip_suffix(')')
+                    .ok_or(DiceError::Malformed("missing closing parenthesis"))?;
+                let params: Vec<&str> = if params.trim().is_empty() {
+                    Vec::new()
+                } else {
+                    params.split(',').map(str::trim).collect()
+                };
+                (head[..open].trim(), params)
+            }
+            None => (head, Vec::new()),
+        };
+        Macro::new(name, &params, body)
+    }
+
+    /// Returns the body with every parameter replaced by its argument.
+    fn substitute(&self, args: &[String]) -> String {
+        let mut body = self.body.clone();
+        for (param, arg) in self.params.iter().zip(args) {
+            // Arguments that aren't a single number or name are parenthesized so that they
+            // keep their meaning inside the body.
+            let arg = if is_atom(arg) {
+                arg.clone()
+            } else {
+                format!("({})", arg)
+            };
+            body = body.replace(&format!("{{{}}}", param), &arg);
+        }
+        body
+    }
+}
+
+/// Returns true if `text` is a single number or name.
+fn is_atom(text: &str) -> bool {
+    text.trim_start_matches('@')
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Default for Macros {
+    fn default() -> Self {
+        Macros {
+            macros: BTreeMap::new(),
+            max_depth: MAX_DEPTH,
+            max_length: MAX_LENGTH,
+        }
+    }
+}
+
+impl Macros {
+    /// Creates an empty set of macros.
+    pub fn new() -> Macros {
+        Default::default()
+    }
+
+    /// Defines a macro, replacing and returning any macro with the same name.
+    pub fn define(&mut self, definition: Macro) -> Option<Macro> {
+        log::info!("Defining macro {}", definition);
+        self.macros.insert(definition.name.clone(), definition)
+    }
+
+    /// Removes and returns the macro with the given name.
+    pub fn remove(&mut self, name: &str) -> Option<Macro> {
+        self.macros.remove(name)
+    }
+
+    /// Returns the macro with the given name.
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    /// Returns every macro, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Macro> {
+        self.macros.values()
+    }
+
+    /// Expands every macro call of an input, which can then be parsed as a dice expression.
+    ///
+    /// Labels and comments are left untouched.
+    pub fn expand(&self, input: &str) -> Result<String, DiceError> {
+        self.expand_nested(input, &mut Expansion::default())
+    }
+
+    /// Expands an input within an expansion in progress.
+    fn expand_nested(&self, input: &str, expansion: &mut Expansion) -> Result<String, DiceError> {
+        let mut output = String::with_capacity(input.len());
+        let mut rest = input;
+        while let Some(c) = rest.chars().next() {
+            let written = output.len();
+            match c {
+                '#' => {
+                    output.push_str(rest);
+                    break;
+                }
+                '[' => {
+                    let end = rest.find(']').map_or(rest.len(), |end| end + 1);
+                    output.push_str(&rest[..end]);
+                    rest = &rest[end..];
+                }
+                c if c.is_ascii_alphanumeric() || c == '_' || c == '@' => {
+                    let end = rest
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '@'))
+                        .unwrap_or(rest.len());
+                    let (word, after) = rest.split_at(end);
+                    match self.macros.get(word) {
+                        Some(definition) if after.starts_with('(') => {
+                            let (args, after) = split_args(&after[1..])?;
+                            let expanded = self.call(definition, &args, expansion)?;
+                            output.push('(');
+                            output.push_str(&expanded);
+                            output.push(')');
+                            rest = after;
+                        }
+                        _ => {
+                            output.push_str(word);
+                            rest = after;
+                        }
+                    }
+                }
+                c => {
+                    output.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+            expansion.length += output.len() - written;
+            if expansion.length > self.max_length {
+                return Err(DiceError::Malformed("macros expand to too long an input"));
+            }
+        }
+        Ok(output)
+    }
+
+    /// Expands a call to a macro with the given arguments.
+    fn call(
+        &self,
+        definition: &Macro,
+        args: &[&str],
+        expansion: &mut Expansion,
+    ) -> Result<String, DiceError> {
+        if expansion.calls.contains(&definition.name) {
+            return Err(DiceError::RecursiveMacro(definition.name.clone()));
+        }
+        if expansion.depth >= self.max_depth {
+            return Err(DiceError::Malformed("macro calls nest too deeply"));
+        }
+        if args.len() != definition.params.len() {
+            return Err(DiceError::Malformed("wrong number of macro arguments"));
+        }
+        expansion.depth += 1;
+        let args = args
+            .iter()
+            .map(|arg| self.expand_nested(arg.trim(), expansion))
+            .collect::<Result<Vec<_>, _>>();
+        let expanded = args.and_then(|args| {
+            expansion.calls.push(definition.name.clone());
+            let expanded = self.expand_nested(&definition.substitute(&args), expansion);
+            expansion.calls.pop();
+            expanded
+        });
+        expansion.depth -= 1;
+        expanded
+    }
+}
+
+/// Splits the arguments of a call at top-level commas, `input` starting right after the opening
+/// parenthesis, and returns them with the input after the closing parenthesis.
+fn split_args(input: &str) -> Result<(Vec<&str>, &str), DiceError> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => {
+                if !(args.is_empty() && input[start..i].trim().is_empty()) {
+                    args.push(&input[start..i]);
+                }
+                return Ok((args, &input[i + 1..]));
+            }
+            ',' if depth == 0 => {
+                args.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    Err(DiceError::Malformed("missing closing parenthesis"))
+}
+
+impl fmt::Display for Macro {
+    /// Writes the definition of the macro, e.g. `attack(bonus) = 1d20+{bonus}`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) = {}",
+            self.name,
+            self.params.join(", "),
+            self.body
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn macros(definitions: &[&str]) -> Macros {
+        let mut macros = Macros::new();
+        for definition in definitions {
+            macros.define(Macro::parse(definition).unwrap());
+        }
+        macros
+    }
+
+    #[test]
+    fn define_macros() {
+        let definition = Macro::parse("attack(bonus) = 1d20+{bonus}").unwrap();
+        assert_eq!(definition.params, vec!["bonus"]);
+        assert_eq!(definition.to_string(), "attack(bonus) = 1d20+{bonus}");
+        assert_eq!(Macro::parse("fireball = 8d6").unwrap().params.len(), 0);
+        assert_eq!(
+            Macro::parse("attack(bonus) = 1d20+{bonsu}"),
+            Err(DiceError::Malformed(
+                "macro body references an unknown parameter"
+            ))
+        );
+        assert_eq!(
+            Macro::parse("2x(a) = {a}"),
+            Err(DiceError::Malformed("invalid macro name"))
+        );
+        let mut macros = macros(&["fireball = 8d6"]);
+        assert!(macros
+            .define(Macro::parse("fireball = 10d6").unwrap())
+            .is_some());
+        assert_eq!(macros.get("fireball").unwrap().body, "10d6");
+        assert!(macros.remove("fireball").is_some());
+        assert_eq!(macros.iter().count(), 0);
+    }
+
+    #[test]
+    fn expand_calls() {
+        let macros = macros(&[
+            "attack(bonus) = 1d20+{bonus}",
+            "damage(dice, bonus) = {dice}d8+{bonus}",
+            "smite(level) = damage(1, 3) + {level}d8[radiant]",
+            "fireball = 8d6",
+        ]);
+        assert_eq!(macros.expand("attack(5) + 1").unwrap(), "(1d20+5) + 1");
+        assert_eq!(macros.expand("damage(2, 1d4+1)").unwrap(), "(2d8+(1d4+1))");
+        assert_eq!(
+            macros.expand("smite(2)").unwrap(),
+            "((1d8+3) + 2d8[radiant])"
+        );
+        assert_eq!(macros.expand("attack(@str)").unwrap(), "(1d20+@str)");
+        assert_eq!(
+            macros.expand("attack(attack(1)) # attack(2)").unwrap(),
+            "(1d20+((1d20+1))) # attack(2)"
+        );
+        assert_eq!(macros.expand("fireball() sum").unwrap(), "(8d6) sum");
+        assert!(parse(&macros.expand("2*smite(3)").unwrap()).is_ok());
+        assert_eq!(
+            macros.expand("attack(1, 2)"),
+            Err(DiceError::Malformed("wrong number of macro arguments"))
+        );
+        assert_eq!(
+            macros.expand("attack(1"),
+            Err(DiceError::Malformed("missing closing parenthesis"))
+        );
+    }
+
+    #[test]
+    fn expand_detects_cycles() {
+        let mut macros = macros(&["ping(x) = pong({x})", "pong(x) = ping({x})"]);
+        assert_eq!(
+            macros.expand("ping(1)"),
+            Err(DiceError::RecursiveMacro("ping".to_string()))
+        );
+        macros.max_depth = 2;
+        macros.define(Macro::parse("a = b()").unwrap());
+        macros.define(Macro::parse("b = c()").unwrap());
+        macros.define(Macro::parse("c = 1").unwrap());
+        assert_eq!(
+            macros.expand("a()"),
+            Err(DiceError::Malformed("macro calls nest too deeply"))
+        );
+        assert_eq!(macros.expand("b()").unwrap(), "((1))");
+    }
+
+    #[test]
+    fn expand_within_limits() {
+        let too_long = Err(DiceError::Malformed("macros expand to too long an input"));
+        let mut macros = macros(&["m0 = 1d6", "twice(x) = {x}+{x}", "one(x) = 1"]);
+        for i in 1..=15 {
+            let body = format!("m{0}()+m{0}()", i - 1);
+            macros.define(Macro::new(&format!("m{}", i), &[], &body).unwrap());
+        }
+        assert!(parse(&macros.expand("m8()").unwrap()).is_ok());
+        assert_eq!(macros.expand("m15()"), too_long);
+        let nested = format!("{}1{}", "twice(".repeat(16), ")".repeat(16));
+        assert_eq!(macros.expand(&nested), too_long);
+
+        // Calls in arguments nest like calls in bodies, even when the arguments are unused.
+        let nested = format!("{}1{}", "one(".repeat(10_000), ")".repeat(10_000));
+        assert_eq!(
+            macros.expand(&nested),
+            Err(DiceError::Malformed("macro calls nest too deeply"))
+        );
+        assert_eq!(macros.expand("one(one(2))").unwrap(), "(1)");
+    }
+}
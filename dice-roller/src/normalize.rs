@@ -0,0 +1,266 @@
+//! Normalization of dice expressions.
+//!
+//! [`Expr::normalize`] rewrites an expression into a canonical form that rolls the same way:
+//! like dice terms of a sum are merged and constants are folded into a single one at the end of
+//! the sum, so `1d20 + 1D6 + 1d6+2 +1` becomes `1d20+2d6+3`.
+
+use crate::expr::{BinOp, DiceTerm, Expr, Repeat, Statement, StatementKind};
+
+impl Expr {
+    /// Returns the canonical form of the expression.
+    ///
+    /// Dice terms are only merged when they have no reroll, explosion or keep modifier, whose
+    /// limits and kept dice apply to a whole term, and labelled sub-expressions are kept apart
+    /// so that their totals are still reported. The first dice term rolled is left as it is,
+    /// since it decides whether the roll is critical when no term has a threshold.
+    pub fn normalize(&self) -> Expr {
+        self.normalize_from(&mut true)
+    }
+
+    /// Returns the canonical form of the expression, `leading` being true until the first dice
+    /// term is reached.
+    fn normalize_from(&self, leading: &mut bool) -> Expr {
+        match self {
+            Expr::Number(_) => self.clone(),
+            Expr::Dice(_) => {
+                *leading = false;
+                self.clone()
+            }
+            Expr::Label(expr, label) => {
+                Expr::Label(Box::new(expr.normalize_from(leading)), label.clone())
+            }
+            Expr::Neg(_) | Expr::Binary(BinOp::Add, _, _) | Expr::Binary(BinOp::Sub, _, _) => {
+                let mut terms = Vec::new();
+                collect_terms(self, false, leading, &mut terms);
+                sum(terms)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.normalize_from(leading);
+                let rhs = rhs.normalize_from(leading);
+                let folded = match (op, constant(&lhs), constant(&rhs)) {
+                    (BinOp::Mul, Some(a), Some(b)) => a.checked_mul(b),
+                    // Dividing by zero is left to fail when rolled.
+                    (BinOp::Div, Some(a), Some(b)) if b != 0 => a.checked_div(b),
+                    _ => None,
+                };
+                // The smallest i64 has no opposite to be written with.
+                match folded.filter(|&value| value != i64::MIN) {
+                    Some(value) => number(value),
+                    None => Expr::Binary(*op, Box::new(lhs), Box::new(rhs)),
+                }
+            }
+        }
+    }
+}
+
+impl Statement {
+    /// Returns the canonical form of the statement, its expression being normalized.
+    pub fn normalize(&self) -> Statement {
+        let kind = match &self.kind {
+            StatementKind::Roll(expr) => StatementKind::Roll(expr.normalize()),
+            StatementKind::Repeat(repeat) => StatementKind::Repeat(Repeat {
+                expr: repeat.expr.normalize(),
+                ..repeat.clone()
+            }),
+        };
+        Statement {
+            kind,
+            comment: self.comment.clone(),
+        }
+    }
+}
+
+/// A normalized term of a sum.
+struct Term {
+    /// Whether the term is subtracted.
+    negative: bool,
+    expr: Expr,
+    /// Whether the term is the first dice term rolled, which is never merged.
+    leading: bool,
+}
+
+/// Collects the normalized terms of a sum, in the order they are rolled.
+fn collect_terms(expr: &Expr, negative: bool, leading: &mut bool, terms: &mut Vec<Term>) {
+    match expr {
+        Expr::Binary(BinOp::Add, lhs, rhs) => {
+            collect_terms(lhs, negative, leading, terms);
+            collect_terms(rhs, negative, leading, terms);
+        }
+        Expr::Binary(BinOp::Sub, lhs, rhs) => {
+            collect_terms(lhs, negative, leading, terms);
+            collect_terms(rhs, !negative, leading, terms);
+        }
+        Expr::Neg(expr) => collect_terms(expr, !negative, leading, terms),
+        expr => {
+            let first = *leading && matches!(expr, Expr::Dice(_));
+            terms.push(Term {
+                negative,
+                expr: expr.normalize_from(leading),
+                leading: first,
+            })
+        }
+    }
+}
+
+/// Builds the sum of normalized terms, merging like dice terms and folding constants.
+fn sum(terms: Vec<Term>) -> Expr {
+    let mut merged: Vec<Term> = Vec::with_capacity(terms.len());
+    let mut total: i64 = 0;
+    for term in terms {
+        let negative = term.negative;
+        if let Some(value) = constant(&term.expr) {
+            let value = if negative {
+                value.checked_neg()
+            } else {
+                Some(value)
+            };
+            // A total of the smallest i64 would have no opposite to be written with.
+            if let Some(sum) = value
+                .and_then(|value| total.checked_add(value))
+                .filter(|&sum| sum != i64::MIN)
+            {
+                total = sum;
+                continue;
+            }
+        }
+        if let (Expr::Dice(dice), false) = (&term.expr, term.leading) {
+            let like = merged
+                .iter_mut()
+                .filter(|other| other.negative == negative && !other.leading)
+                .find_map(|other| match &mut other.expr {
+                    Expr::Dice(other) if mergeable(other, dice) => Some(other),
+                    _ => None,
+                });
+            if let Some(count) = like
+                .as_ref()
+                .and_then(|like| like.count.checked_add(dice.count))
+            {
+                like.expect("a like term was found").count = count;
+                continue;
+            }
+        }
+        merged.push(term);
+    }
+    if total != 0 || merged.is_empty() {
+        merged.push(Term {
+            negative: total < 0,
+            expr: Expr::Number(total.abs()),
+            leading: false,
+        });
+    }
+
+    let mut terms = merged.into_iter().map(|term| (term.negative, term.expr));
+    let (negative, first) = terms.next().expect("a sum has at least one term");
+    let mut sum = if negative {
+        Expr::Neg(Box::new(first))
+    } else {
+        first
+    };
+    for (negative, term) in terms {
+        let op = if negative { BinOp::Sub } else { BinOp::Add };
+        sum = Expr::Binary(op, Box::new(sum), Box::new(term));
+    }
+    sum
+}
+
+/// Returns true if two dice terms can be rolled as a single term.
+fn mergeable(term: &DiceTerm, other: &DiceTerm) -> bool {
+    term.reroll.is_none()
+        && term.explode.is_none()
+        && term.keep.is_none()
+        && DiceTerm {
+            count: other.count,
+            ..term.clone()
+        } == *other
+}
+
+/// Returns the value of a constant expression, a number or its opposite.
+fn constant(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Number(value) => Some(*value),
+        Expr::Neg(expr) => match **expr {
+            Expr::Number(value) => value.checked_neg(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the expression of a constant, negative constants being the opposite of a number as
+/// they are parsed.
+fn number(value: i64) -> Expr {
+    match value.checked_neg() {
+        Some(negated) if value < 0 => Expr::Neg(Box::new(Expr::Number(negated))),
+        _ => Expr::Number(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse, parse_statement};
+
+    fn normalize(input: &str) -> String {
+        let statement = parse_statement(input).unwrap().normalize();
+        assert_eq!(
+            parse_statement(&statement.to_string()),
+            Ok(statement.clone())
+        );
+        statement.to_string()
+    }
+
+    #[test]
+    fn merge_dice_and_constants() {
+        assert_eq!(normalize("1d20 + 1D6 + 1d6+2 +1"), "1d20+2d6+3");
+        assert_eq!(normalize("1 + 1d20 + 2d6 - 1d6 + 1d6"), "1d20+3d6-1d6+1");
+        assert_eq!(normalize("-(1d4+2)"), "-1d4-2");
+        assert_eq!(normalize("2*3 + 1d6 - 6"), "1d6");
+        assert_eq!(normalize("3-5"), "-2");
+        assert_eq!(normalize("1d6-1d6"), "1d6-1d6");
+        assert_eq!(normalize("--1d8"), "1d8");
+        assert_eq!(normalize("1d20 + 4dF + 2dF + 1dF.1"), "1d20+6dF+1dF.1");
+        assert_eq!(normalize("1d20 + (1d8+1d8)*(2+2)"), "1d20+2d8*4");
+        assert_eq!(normalize("1d6/(1-1)"), "1d6/0");
+        assert_eq!(
+            normalize("-9223372036854775807-1"),
+            "-1-9223372036854775807"
+        );
+        assert_eq!(
+            normalize("-4611686018427387904*2"),
+            "-4611686018427387904*2"
+        );
+    }
+
+    #[test]
+    fn keep_leading_term() {
+        // The first dice term decides whether the roll is critical, so it is never merged.
+        assert_eq!(normalize("1d20 + 1d20 + 1d20"), "1d20+2d20");
+        assert_eq!(normalize("1 + 2d6 - 1d6 + 1d6"), "2d6-1d6+1d6+1");
+        assert_eq!(normalize("(1d8+1d8)*(2+2)"), "(1d8+1d8)*4");
+        assert_eq!(normalize("2 * 3 + 4dF + 2dF"), "4dF+2dF+6");
+        assert_eq!(normalize("3d6[a] + 3d6 + 3d6"), "3d6[a]+6d6");
+    }
+
+    #[test]
+    fn keep_terms_apart() {
+        assert_eq!(normalize("4d6kh3 + 4d6kh3"), "4d6kh3+4d6kh3");
+        assert_eq!(normalize("1d6! + 1d6!"), "1d6!+1d6!");
+        assert_eq!(normalize("1d20 + 5d10>=8 + 5d10>=8"), "1d20+10d10>=8");
+        assert_eq!(
+            normalize("2d6[fire] + 1d6[fire] + 1"),
+            "2d6[fire]+1d6[fire]+1"
+        );
+        assert_eq!(normalize("1d20 + 2 + 3 # attack"), "1d20+5 # attack");
+        assert_eq!(
+            normalize("6x 1d20+1d6+1d6 sum # stats"),
+            "6x 1d20+2d6 sum # stats"
+        );
+        assert_eq!(
+            normalize("2d6[fire] + 1d6 + 1d6 # burn"),
+            "2d6[fire]+2d6 # burn"
+        );
+        assert_eq!(
+            parse("4000000000d6 + 4000000000d6").unwrap().normalize(),
+            parse("4000000000d6 + 4000000000d6").unwrap()
+        );
+    }
+}
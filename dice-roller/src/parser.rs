@@ -0,0 +1,1357 @@
+//! Recursive descent parser for dice expressions.
+//!
+//! The grammar, from lowest to highest precedence:
+//!
+//! ```text
+//! statement := (number ('x' | 'X') expr list-op* | expr) comment?
+//! list-op   := 'sort' 'desc'? | 'sum' | 'max' | 'min' | 'count' condition
+//! comment   := '#' text
+//! expr      := term (('+' | '-') term)*
+//! term      := unary (('*' | '/') unary)*
+//! unary     := '-' unary | primary ('[' text ']')?
+//! primary   := number | dice | variable | '(' expr ')'
+//! variable  := '@' (letter | digit | '_')+
+//! dice      := number? ('d' | 'D') (number | fudge) modifier*
+//! fudge     := ('f' | 'F') ('.1' | '.2')?
+//! ```
+//!
+//! Variables are resolved while parsing, through the [`Variables`] given to [`parse_with`], and
+//! replaced with the expression they hold. The value of each variable is parsed once, and the
+//! expressions variables expand to can have at most [`MAX_EXPANSION`] nodes in total.
+//!
+//! A term followed by a label in brackets, such as `2d6[fire]`, keeps the label in the result
+//! of the roll. A statement can end with a comment, such as `1d20+5 # longsword attack`, which
+//! is kept apart from the labels.
+//!
+//! Fudge dice (`4dF`) read -1, 0 or +1 on two faces each, `dF.1` has a single face each of -1
+//! and +1 and four blank faces.
+//!
+//! Dice modifiers are written right after the number of sides:
+//!
+//! * `khN` / `kN` keeps the N highest dice, `klN` the N lowest.
+//! * `dhN` drops the N highest dice, `dlN` the N lowest.
+//! * `rN` or `roN` rerolls once the dice matching a condition, `rrN` rerolls them until they
+//!   no longer match.
+//! * `!` explodes, `!!` compounds and `!p` penetrates on the highest face, or on the faces
+//!   matching an optional condition such as `!>=9`.
+//! * `csN` and `cfN` count the dice matching a condition as critical successes and failures,
+//!   instead of the highest and lowest faces, which aren't critical for Fudge dice.
+//! * A condition such as `>=8` turns the term into a dice pool counting successes, `fN`
+//!   subtracts a failure for each die matching a condition and `dblN` counts the successes
+//!   matching a condition twice.
+//!
+//! Conditions are a comparison (`=`, `>`, `>=`, `<`, `<=`) followed by a number, a bare number
+//! meaning `=`.
+//!
+//! Syntax errors are returned as [`DiceError::Syntax`], with the span of the input they are
+//! about, which [`DiceError::render`] shows under the input.
+//!
+//! Expressions can be nested at most [`MAX_DEPTH`] levels deep, each parenthesis, unary minus
+//! and label counting as a level, and have at most [`MAX_OPERATORS`] binary operators, so that
+//! they can be parsed and rolled without exhausting the stack.
+
+use crate::error::{DiceError, ParseError};
+use crate::expr::{
+    Aggregate, BinOp, Comparison, Condition, DiceTerm, Explode, ExplodeKind, Expr, Fudge, Keep,
+    Pool, Repeat, Reroll, Sort, Statement, StatementKind,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+
+/// What the parser expects where an operand is missing.
+const OPERAND: &str = "number, dice, variable or '('";
+
+/// Maximum depth of a parsed expression.
+pub const MAX_DEPTH: usize = 128;
+
+/// Maximum number of binary operators of a parsed expression, variables included.
+pub const MAX_OPERATORS: usize = 1_000;
+
+/// Maximum number of nodes variables can expand to in a parsed expression.
+pub const MAX_EXPANSION: usize = 10_000;
+
+/// Resolves the variables referenced in dice expressions, such as `@str_mod`.
+///
+/// The value of a variable is itself a dice expression, either a plain number like `3` or
+/// dice like `1d6+1`, which can reference other variables.
+pub trait Variables {
+    /// Returns the value of a variable, `None` if it is unknown.
+    fn get(&self, name: &str) -> Option<String>;
+}
+
+impl<S: BuildHasher> Variables for HashMap<String, String, S> {
+    fn get(&self, name: &str) -> Option<String> {
+        HashMap::get(self, name).cloned()
+    }
+}
+
+impl Variables for BTreeMap<String, String> {
+    fn get(&self, name: &str) -> Option<String> {
+        BTreeMap::get(self, name).cloned()
+    }
+}
+
+/// The variables of expressions parsed without a context, none of them being known.
+struct NoVariables;
+
+impl Variables for NoVariables {
+    fn get(&self, _: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Parses a dice expression such as `2d6+1d4+3` or `(1d8+2)*2`.
+pub fn parse(input: &str) -> Result<Expr, DiceError> {
+    parse_with(input, &NoVariables)
+}
+
+/// Parses a dice expression, resolving its variables such as `@str_mod` in `variables`.
+///
+/// A trailing comment is skipped, [`parse_statement_with`] keeping it.
+pub fn parse_with(input: &str, variables: &dyn Variables) -> Result<Expr, DiceError> {
+    let mut parser = Parser::new(input, variables);
+    let expr = parser.expr()?;
+    parser.comment()?;
+    parser.finish()?;
+    Ok(expr)
+}
+
+/// Parses a statement, either a dice expression or a repeated one such as `6x 4d6kh3`.
+///
+/// A repeated expression can be followed by `sort` or `sort desc` to order the rolls by total,
+/// and by an aggregate of the totals: `sum`, `max`, `min` or `count` and a condition, such as
+/// `count>=15`.
+pub fn parse_statement(input: &str) -> Result<Statement, DiceError> {
+    parse_statement_with(input, &NoVariables)
+}
+
+/// Parses a statement, resolving its variables in `variables`.
+pub fn parse_statement_with(
+    input: &str,
+    variables: &dyn Variables,
+) -> Result<Statement, DiceError> {
+    let mut parser = Parser::new(input, variables);
+    let statement = parser.statement()?;
+    parser.finish()?;
+    Ok(statement)
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    variables: &'a dyn Variables,
+    /// Variables whose value is being parsed, to detect variables referring to themselves.
+    resolving: Vec<String>,
+    /// Variables whose value was already parsed.
+    resolved: HashMap<String, Resolved>,
+    /// Number of nodes the variables referenced so far expanded to.
+    expanded: usize,
+    /// Number of binary operators parsed so far, variables included.
+    operators: usize,
+    /// Depth of the expression being parsed.
+    depth: usize,
+}
+
+/// The parsed value of a variable.
+struct Resolved {
+    expr: Expr,
+    /// Number of nodes of the expression.
+    nodes: usize,
+    /// Number of binary operators of the expression.
+    operators: usize,
+    /// Depth of the expression, 0 for a single node.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, variables: &'a dyn Variables) -> Parser<'a> {
+        Parser {
+            input: input.as_bytes(),
+            pos: 0,
+            variables,
+            resolving: Vec::new(),
+            resolved: HashMap::new(),
+            expanded: 0,
+            operators: 0,
+            depth: 0,
+        }
+    }
+
+    /// Checks that the whole input was parsed.
+    fn finish(&mut self) -> Result<(), DiceError> {
+        self.skip_whitespace();
+        if self.pos < self.input.len() {
+            return Err(self.error(
+                "unexpected trailing characters",
+                Some("operator or end of input"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns a syntax error about the current character.
+    fn error(&self, message: &'static str, expected: Option<&'static str>) -> DiceError {
+        let end = (self.pos + 1).min(self.input.len());
+        DiceError::Syntax(ParseError {
+            message,
+            expected,
+            span: self.pos..end,
+        })
+    }
+
+    /// Returns a syntax error about the input parsed since `start`.
+    fn error_since(&self, start: usize, message: &'static str) -> DiceError {
+        DiceError::Syntax(ParseError {
+            message,
+            expected: None,
+            span: start..self.pos,
+        })
+    }
+
+    /// Locates an error at the input parsed since `start`, e.g. the number it is about.
+    fn spanned(&self, start: usize, error: DiceError) -> DiceError {
+        DiceError::Spanned(Box::new(error), start..self.pos)
+    }
+
+    /// Counts the binary operator at the current character.
+    fn operator(&mut self) -> Result<(), DiceError> {
+        if self.operators == MAX_OPERATORS {
+            return Err(self.error("expression has too many operators", None));
+        }
+        self.operators += 1;
+        Ok(())
+    }
+
+    /// Goes one level deeper in the expression, the current character nesting what follows.
+    fn deepen(&mut self) -> Result<(), DiceError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("expression nested too deeply", None));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.input.len() && self.input[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    /// Returns the next non-whitespace character without consuming it.
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    /// Returns true and consumes a keyword if it comes next, ignoring case.
+    fn keyword(&mut self, word: &str) -> bool {
+        self.skip_whitespace();
+        let end = self.pos + word.len();
+        let matches = self
+            .input
+            .get(self.pos..end)
+            .is_some_and(|next| next.eq_ignore_ascii_case(word.as_bytes()))
+            && !self.input.get(end).is_some_and(u8::is_ascii_alphabetic);
+        if matches {
+            self.pos = end;
+        }
+        matches
+    }
+
+    fn statement(&mut self) -> Result<Statement, DiceError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.at_digit() {
+            let times = self.number()?;
+            if self
+                .input
+                .get(self.pos)
+                .is_some_and(|c| c.eq_ignore_ascii_case(&b'x'))
+            {
+                let times = u32::try_from(times)
+                    .map_err(|_| self.spanned(start, DiceError::TooManyRepeats))?;
+                self.pos += 1;
+                if times == 0 {
+                    return Err(
+                        self.error_since(start, "expression must be repeated at least once")
+                    );
+                }
+                let kind = StatementKind::Repeat(self.repeat(times)?);
+                let comment = self.comment()?;
+                return Ok(Statement { kind, comment });
+            }
+            self.pos = start;
+        }
+        let kind = StatementKind::Roll(self.expr()?);
+        let comment = self.comment()?;
+        Ok(Statement { kind, comment })
+    }
+
+    /// Parses the comment ending the input, if any.
+    fn comment(&mut self) -> Result<Option<String>, DiceError> {
+        if self.peek() != Some(b'#') {
+            return Ok(None);
+        }
+        let comment = self.label_text(self.input.len())?;
+        self.pos = self.input.len();
+        Ok(Some(comment))
+    }
+
+    /// Returns the trimmed text from after the current character up to `end`, which can't be
+    /// empty.
+    fn label_text(&self, end: usize) -> Result<String, DiceError> {
+        let text = std::str::from_utf8(&self.input[self.pos + 1..end])
+            .expect("labels are delimited by ASCII characters")
+            .trim();
+        if text.is_empty() {
+            return Err(DiceError::Syntax(ParseError {
+                message: "label can't be empty",
+                expected: None,
+                span: self.pos..(end + 1).min(self.input.len()),
+            }));
+        }
+        Ok(text.to_string())
+    }
+
+    /// Parses a repeated expression, the repeat count and `x` being already consumed.
+    fn repeat(&mut self, times: u32) -> Result<Repeat, DiceError> {
+        let expr = self.expr()?;
+        let mut repeat = Repeat {
+            times,
+            expr,
+            sort: None,
+            aggregate: None,
+        };
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            if self.keyword("sort") {
+                if repeat.sort.is_some() {
+                    return Err(self.error_since(start, "rolls can only be sorted once"));
+                }
+                repeat.sort = Some(if self.keyword("desc") {
+                    Sort::Descending
+                } else {
+                    Sort::Ascending
+                });
+                continue;
+            }
+            let aggregate = if self.keyword("sum") {
+                Aggregate::Sum
+            } else if self.keyword("max") {
+                Aggregate::Max
+            } else if self.keyword("min") {
+                Aggregate::Min
+            } else if self.keyword("count") {
+                let condition = self.condition()?.ok_or_else(|| {
+                    self.error("missing condition after count", Some("condition"))
+                })?;
+                Aggregate::Count(condition)
+            } else {
+                return Ok(repeat);
+            };
+            if repeat.aggregate.is_some() {
+                return Err(self.error_since(start, "rolls can only be aggregated once"));
+            }
+            repeat.aggregate = Some(aggregate);
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, DiceError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(b'+') => BinOp::Add,
+                Some(b'-') => BinOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.operator()?;
+            self.pos += 1;
+            let rhs = self.term()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, DiceError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(b'*') => BinOp::Mul,
+                Some(b'/') => BinOp::Div,
+                _ => return Ok(lhs),
+            };
+            self.operator()?;
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, DiceError> {
+        if self.peek() == Some(b'-') {
+            self.deepen()?;
+            self.pos += 1;
+            let operand = self.unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Neg(Box::new(operand)));
+        }
+        let expr = self.primary()?;
+        if self.peek() != Some(b'[') {
+            return Ok(expr);
+        }
+        // The label nests the operand one level deeper.
+        self.deepen()?;
+        self.depth -= 1;
+        let end = match self.input[self.pos..].iter().position(|&c| c == b']') {
+            Some(end) => end,
+            None => {
+                self.pos = self.input.len();
+                return Err(self.error("missing closing bracket", Some("']'")));
+            }
+        };
+        let label = self.label_text(self.pos + end)?;
+        self.pos += end + 1;
+        Ok(Expr::Label(Box::new(expr), label))
+    }
+
+    fn primary(&mut self) -> Result<Expr, DiceError> {
+        match self.peek() {
+            Some(b'(') => {
+                self.deepen()?;
+                self.pos += 1;
+                let expr = self.expr()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.error("missing closing parenthesis", Some("')'")));
+                }
+                self.pos += 1;
+                self.depth -= 1;
+                Ok(expr)
+            }
+            Some(b'd') | Some(b'D') => self.dice(1),
+            Some(b'@') => self.variable(),
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.pos;
+                let number = self.number()?;
+                match self.input.get(self.pos) {
+                    Some(b'd') | Some(b'D') => {
+                        let count = u32::try_from(number)
+                            .map_err(|_| self.spanned(start, DiceError::TooManyDice))?;
+                        if count == 0 {
+                            return Err(self.spanned(start, DiceError::ZeroDice));
+                        }
+                        self.dice(count)
+                    }
+                    _ => {
+                        if number > i64::MAX as u64 {
+                            return Err(self.spanned(start, DiceError::Overflow));
+                        }
+                        Ok(Expr::Number(number as i64))
+                    }
+                }
+            }
+            Some(_) => Err(self.error("unexpected character", Some(OPERAND))),
+            None => Err(self.error("unexpected end of expression", Some(OPERAND))),
+        }
+    }
+
+    /// Parses the `dN` part of a dice term, the separator being the current character.
+    fn dice(&mut self, count: u32) -> Result<Expr, DiceError> {
+        self.pos += 1;
+        let fudge = self
+            .input
+            .get(self.pos)
+            .is_some_and(|c| c.eq_ignore_ascii_case(&b'f'));
+        let mut term = if fudge {
+            DiceTerm::fudge(count, self.fudge()?)
+        } else {
+            if !self.at_digit() {
+                return Err(self.error(
+                    "right side of separator is not an int",
+                    Some("number of sides or 'F'"),
+                ));
+            }
+            let start = self.pos;
+            let sides = self.number()?;
+            let sides = match u32::try_from(sides) {
+                Ok(sides) if sides > 0 => sides,
+                _ => return Err(self.spanned(start, DiceError::SidesOutOfRange(sides))),
+            };
+            DiceTerm::new(count, sides)
+        };
+        while let Some(c) = self.input.get(self.pos) {
+            let start = self.pos;
+            match c.to_ascii_lowercase() {
+                b'd' if self
+                    .input
+                    .get(self.pos..self.pos + 3)
+                    .is_some_and(|next| next.eq_ignore_ascii_case(b"dbl")) =>
+                {
+                    self.pos += 3;
+                    let double = self.condition()?.ok_or_else(|| {
+                        self.error("missing condition after doubles", Some("condition"))
+                    })?;
+                    self.pool(start, &mut term)?.double = Some(double);
+                }
+                b'k' | b'd' => self.keep(&mut term)?,
+                b'r' => self.reroll(&mut term)?,
+                b'c' => self.crit(&mut term)?,
+                b'f' => {
+                    self.pos += 1;
+                    let failure = self.condition()?.ok_or_else(|| {
+                        self.error("missing condition after failures", Some("condition"))
+                    })?;
+                    self.pool(start, &mut term)?.failure = Some(failure);
+                }
+                b'!' => self.explode(&mut term)?,
+                b'>' | b'<' | b'=' => {
+                    let success = self.condition()?.expect("a comparison was found");
+                    if term.pool.is_some() {
+                        return Err(self.error_since(start, "dice pool can only have one target"));
+                    }
+                    term.pool = Some(Pool {
+                        success,
+                        failure: None,
+                        double: None,
+                    });
+                }
+                _ => break,
+            }
+        }
+        Ok(Expr::Dice(term))
+    }
+
+    /// Parses a variable reference and returns the expression it holds, the `@` being the
+    /// current character.
+    fn variable(&mut self) -> Result<Expr, DiceError> {
+        let position = self.pos;
+        self.pos += 1;
+        let start = self.pos;
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|&c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.error("missing variable name", Some("variable name")));
+        }
+        let name = std::str::from_utf8(&self.input[start..self.pos])
+            .expect("variable names are ASCII")
+            .to_string();
+        if self.resolving.contains(&name) {
+            return Err(self.error_since(position, "variable refers to itself"));
+        }
+        if !self.resolved.contains_key(&name) {
+            let value = self
+                .variables
+                .get(&name)
+                .ok_or_else(|| DiceError::UnknownVariable {
+                    name: name.clone(),
+                    position,
+                })?;
+            let expr = self
+                .value(name.clone(), &value)
+                .map_err(|error| match error {
+                    // Report errors in the value of the variable at the reference in this input.
+                    DiceError::UnknownVariable { name, .. } => {
+                        DiceError::UnknownVariable { name, position }
+                    }
+                    DiceError::Syntax(error) => DiceError::Syntax(ParseError {
+                        span: position..self.pos,
+                        ..error
+                    }),
+                    DiceError::Spanned(error, _) => DiceError::Spanned(error, position..self.pos),
+                    error => error,
+                })?;
+            let (nodes, operators, depth) = size(&expr);
+            self.resolved.insert(
+                name.clone(),
+                Resolved {
+                    expr,
+                    nodes,
+                    operators,
+                    depth,
+                },
+            );
+        }
+        let resolved = &self.resolved[&name];
+        if self.depth + resolved.depth > MAX_DEPTH {
+            return Err(self.error_since(position, "expression nested too deeply"));
+        }
+        self.operators += resolved.operators;
+        if self.operators > MAX_OPERATORS {
+            return Err(self.error_since(position, "expression has too many operators"));
+        }
+        self.expanded += resolved.nodes;
+        if self.expanded > MAX_EXPANSION {
+            return Err(self.error_since(position, "variables expand to too large an expression"));
+        }
+        Ok(resolved.expr.clone())
+    }
+
+    /// Parses the value of a variable, one level deeper than its reference.
+    fn value(&mut self, name: String, value: &str) -> Result<Expr, DiceError> {
+        let mut parser = Parser {
+            input: value.as_bytes(),
+            pos: 0,
+            variables: self.variables,
+            resolving: std::mem::take(&mut self.resolving),
+            resolved: std::mem::take(&mut self.resolved),
+            expanded: self.expanded,
+            operators: self.operators,
+            depth: self.depth,
+        };
+        parser.resolving.push(name);
+        let expr = parser
+            .deepen()
+            .and_then(|_| parser.expr())
+            .and_then(|expr| parser.finish().map(|_| expr));
+        parser.resolving.pop();
+        self.resolving = parser.resolving;
+        self.resolved = parser.resolved;
+        expr
+    }
+
+    /// Parses the variant of a Fudge die, the `F` being the current character.
+    fn fudge(&mut self) -> Result<Fudge, DiceError> {
+        self.pos += 1;
+        if self.input.get(self.pos) != Some(&b'.') {
+            return Ok(Fudge::Standard);
+        }
+        self.pos += 1;
+        match self.input.get(self.pos) {
+            Some(b'1') => {
+                self.pos += 1;
+                Ok(Fudge::Single)
+            }
+            Some(b'2') => {
+                self.pos += 1;
+                Ok(Fudge::Standard)
+            }
+            _ => Err(self.error("invalid Fudge dice variant", Some("'1' or '2'"))),
+        }
+    }
+
+    /// Returns the dice pool of a term, which must already have a success target, the modifier
+    /// needing it starting at `start`.
+    fn pool<'t>(&self, start: usize, term: &'t mut DiceTerm) -> Result<&'t mut Pool, DiceError> {
+        term.pool
+            .as_mut()
+            .ok_or_else(|| self.error_since(start, "dice pool needs a success target first"))
+    }
+
+    /// Parses a keep or drop modifier, the `k` or `d` being the current character.
+    fn keep(&mut self, term: &mut DiceTerm) -> Result<(), DiceError> {
+        let start = self.pos;
+        let keep = self.input[self.pos].eq_ignore_ascii_case(&b'k');
+        self.pos += 1;
+        let highest = match self.input.get(self.pos).map(u8::to_ascii_lowercase) {
+            Some(b'h') => true,
+            Some(b'l') => false,
+            Some(c) if keep && c.is_ascii_digit() => {
+                self.pos -= 1;
+                true
+            }
+            _ => return Err(self.error("missing direction after keep or drop", Some("'h' or 'l'"))),
+        };
+        self.pos += 1;
+        if !self.at_digit() {
+            return Err(self.error("missing number of dice to keep or drop", Some("number")));
+        }
+        let number_start = self.pos;
+        let number = self.number()?;
+        let number = u32::try_from(number)
+            .map_err(|_| self.spanned(number_start, DiceError::TooManyDice))?;
+        if term.keep.is_some() {
+            return Err(self.error_since(start, "dice can only be kept or dropped once"));
+        }
+        term.keep = Some(match (keep, highest) {
+            (true, true) => Keep::Highest(number),
+            (true, false) => Keep::Lowest(number),
+            (false, true) => Keep::DropHighest(number),
+            (false, false) => Keep::DropLowest(number),
+        });
+        Ok(())
+    }
+
+    /// Parses a reroll modifier, the `r` being the current character.
+    fn reroll(&mut self, term: &mut DiceTerm) -> Result<(), DiceError> {
+        let start = self.pos;
+        self.pos += 1;
+        let recursive = match self.input.get(self.pos).map(u8::to_ascii_lowercase) {
+            Some(b'r') => true,
+            Some(b'o') => false,
+            _ => {
+                self.pos -= 1;
+                false
+            }
+        };
+        self.pos += 1;
+        let condition = self
+            .condition()?
+            .ok_or_else(|| self.error("missing condition after reroll", Some("condition")))?;
+        if term.reroll.is_some() {
+            return Err(self.error_since(start, "dice can only be rerolled once"));
+        }
+        term.reroll = Some(Reroll {
+            recursive,
+            condition,
+        });
+        Ok(())
+    }
+
+    /// Parses a critical success or failure threshold, the `c` being the current character.
+    fn crit(&mut self, term: &mut DiceTerm) -> Result<(), DiceError> {
+        let start = self.pos;
+        self.pos += 1;
+        let success = match self.input.get(self.pos).map(u8::to_ascii_lowercase) {
+            Some(b's') => true,
+            Some(b'f') => false,
+            _ => return Err(self.error("missing kind of critical threshold", Some("'s' or 'f'"))),
+        };
+        self.pos += 1;
+        let condition = self
+            .condition()?
+            .ok_or_else(|| self.error("missing condition after critical", Some("condition")))?;
+        let threshold = if success {
+            &mut term.crit_success
+        } else {
+            &mut term.crit_failure
+        };
+        if threshold.is_some() {
+            return Err(self.error_since(start, "critical threshold can only be set once"));
+        }
+        *threshold = Some(condition);
+        Ok(())
+    }
+
+    /// Parses an explode modifier, the `!` being the current character.
+    fn explode(&mut self, term: &mut DiceTerm) -> Result<(), DiceError> {
+        let start = self.pos;
+        self.pos += 1;
+        let kind = match self.input.get(self.pos).map(u8::to_ascii_lowercase) {
+            Some(b'!') => ExplodeKind::Compound,
+            Some(b'p') => ExplodeKind::Penetrate,
+            _ => ExplodeKind::Standard,
+        };
+        if kind != ExplodeKind::Standard {
+            self.pos += 1;
+        }
+        let condition = self.condition()?;
+        if term.explode.is_some() {
+            return Err(self.error_since(start, "dice can only explode once"));
+        }
+        term.explode = Some(Explode { kind, condition });
+        Ok(())
+    }
+
+    /// Parses an optional condition on the face of a die.
+    fn condition(&mut self) -> Result<Option<Condition>, DiceError> {
+        let comparison = match (self.input.get(self.pos), self.input.get(self.pos + 1)) {
+            (Some(b'>'), Some(b'=')) => Comparison::GreaterOrEqual,
+            (Some(b'<'), Some(b'=')) => Comparison::LessOrEqual,
+            (Some(b'>'), _) => Comparison::Greater,
+            (Some(b'<'), _) => Comparison::Less,
+            (Some(b'='), _) => Comparison::Equal,
+            (Some(c), _) if c.is_ascii_digit() => Comparison::Equal,
+            _ => return Ok(None),
+        };
+        self.pos += match comparison {
+            Comparison::GreaterOrEqual | Comparison::LessOrEqual => 2,
+            Comparison::Equal if self.at_digit() => 0,
+            _ => 1,
+        };
+        if !self.at_digit() {
+            return Err(self.error("missing number after comparison", Some("number")));
+        }
+        let start = self.pos;
+        let value = self.number()?;
+        let value = i64::try_from(value).map_err(|_| self.spanned(start, DiceError::Overflow))?;
+        Ok(Some(Condition { comparison, value }))
+    }
+
+    /// Returns true if the current character is a digit.
+    fn at_digit(&self) -> bool {
+        self.input.get(self.pos).is_some_and(u8::is_ascii_digit)
+    }
+
+    /// Parses an unsigned integer starting at the current position.
+    fn number(&mut self) -> Result<u64, DiceError> {
+        let start = self.pos;
+        let mut value = Some(0u64);
+        while let Some(c) = self.input.get(self.pos).filter(|c| c.is_ascii_digit()) {
+            value = value
+                .and_then(|value| value.checked_mul(10))
+                .and_then(|value| value.checked_add((c - b'0') as u64));
+            self.pos += 1;
+        }
+        value.ok_or_else(|| self.spanned(start, DiceError::Overflow))
+    }
+}
+
+/// Returns the number of nodes, the number of binary operators and the depth of an expression.
+///
+/// Operations chained to the left such as `1+2+3` are at the depth of their first operand,
+/// while an operation on the right of another one counts as a level, as if in parentheses.
+fn size(expr: &Expr) -> (usize, usize, usize) {
+    match expr {
+        Expr::Number(_) | Expr::Dice(_) => (1, 0, 0),
+        Expr::Neg(expr) | Expr::Label(expr, _) => {
+            let (nodes, operators, depth) = size(expr);
+            (nodes + 1, operators, depth + 1)
+        }
+        Expr::Binary(..) => {
+            let (first, operations) = expr.chain();
+            let (mut nodes, mut operators, mut depth) = size(first);
+            for (_, operand) in operations {
+                let (operand_nodes, operand_operators, operand_depth) = size(operand);
+                let nested = matches!(operand, Expr::Binary(..)) as usize;
+                nodes += operand_nodes + 1;
+                operators += operand_operators + 1;
+                depth = depth.max(operand_depth + nested);
+            }
+            (nodes, operators, depth)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dice(count: u32, sides: u32) -> Box<Expr> {
+        Box::new(Expr::Dice(DiceTerm::new(count, sides)))
+    }
+
+    /// Returns the message of the syntax error a parse failed with.
+    fn syntax_error<T: std::fmt::Debug>(result: Result<T, DiceError>) -> &'static str {
+        match result {
+            Err(DiceError::Syntax(error)) => error.message,
+            result => panic!("{:?} is not a syntax error", result),
+        }
+    }
+
+    #[test]
+    fn parse_single_dice() {
+        assert_eq!(parse("3d6"), Ok(*dice(3, 6)));
+        assert_eq!(parse("D20"), Ok(*dice(1, 20)));
+    }
+
+    #[test]
+    fn parse_precedence() {
+        let expected = Expr::Binary(
+            BinOp::Add,
+            dice(2, 6),
+            Box::new(Expr::Binary(
+                BinOp::Mul,
+                dice(1, 4),
+                Box::new(Expr::Number(3)),
+            )),
+        );
+        assert_eq!(parse("2d6 + 1d4 * 3"), Ok(expected));
+    }
+
+    #[test]
+    fn parse_parentheses_and_unary_minus() {
+        let expected = Expr::Binary(
+            BinOp::Mul,
+            Box::new(Expr::Binary(
+                BinOp::Add,
+                dice(1, 8),
+                Box::new(Expr::Number(2)),
+            )),
+            Box::new(Expr::Neg(Box::new(Expr::Number(2)))),
+        );
+        assert_eq!(parse("(1d8+2)*-2"), Ok(expected));
+    }
+
+    #[test]
+    fn parse_keep_and_drop() {
+        let keep = |input| match parse(input) {
+            Ok(Expr::Dice(term)) => term.keep,
+            _ => panic!("{} is not a dice term", input),
+        };
+        assert_eq!(keep("4d6kh3"), Some(Keep::Highest(3)));
+        assert_eq!(keep("4d6k3"), Some(Keep::Highest(3)));
+        assert_eq!(keep("2d20kl1"), Some(Keep::Lowest(1)));
+        assert_eq!(keep("5d10dl2"), Some(Keep::DropLowest(2)));
+        assert_eq!(keep("5d10DH2"), Some(Keep::DropHighest(2)));
+        assert!(parse("4d6kh").is_err());
+        assert!(parse("4d6d2").is_err());
+        assert!(parse("4d6kh3kl1").is_err());
+    }
+
+    #[test]
+    fn parse_explode() {
+        let explode = |input| match parse(input) {
+            Ok(Expr::Dice(term)) => term.explode,
+            _ => panic!("{} is not a dice term", input),
+        };
+        let condition = |comparison, value| Some(Condition { comparison, value });
+        assert_eq!(
+            explode("3d6!"),
+            Some(Explode {
+                kind: ExplodeKind::Standard,
+                condition: None
+            })
+        );
+        assert_eq!(
+            explode("3d6!!"),
+            Some(Explode {
+                kind: ExplodeKind::Compound,
+                condition: None
+            })
+        );
+        assert_eq!(
+            explode("3d6!p>4"),
+            Some(Explode {
+                kind: ExplodeKind::Penetrate,
+                condition: condition(Comparison::Greater, 4)
+            })
+        );
+        assert_eq!(
+            explode("5d10!>=9kh3"),
+            Some(Explode {
+                kind: ExplodeKind::Standard,
+                condition: condition(Comparison::GreaterOrEqual, 9)
+            })
+        );
+        assert_eq!(
+            explode("3d6!5"),
+            Some(Explode {
+                kind: ExplodeKind::Standard,
+                condition: condition(Comparison::Equal, 5)
+            })
+        );
+        assert!(parse("3d6!>").is_err());
+        assert!(parse("3d6!!!").is_err());
+    }
+
+    #[test]
+    fn parse_reroll() {
+        let reroll = |input| match parse(input) {
+            Ok(Expr::Dice(term)) => term.reroll,
+            _ => panic!("{} is not a dice term", input),
+        };
+        let reroll_on = |recursive, comparison, value| {
+            Some(Reroll {
+                recursive,
+                condition: Condition { comparison, value },
+            })
+        };
+        assert_eq!(reroll("2d6r1"), reroll_on(false, Comparison::Equal, 1));
+        assert_eq!(reroll("2d6ro<3"), reroll_on(false, Comparison::Less, 3));
+        assert_eq!(
+            reroll("2d6rr<=2"),
+            reroll_on(true, Comparison::LessOrEqual, 2)
+        );
+        assert!(parse("2d6r").is_err());
+        assert!(parse("2d6rr").is_err());
+    }
+
+    #[test]
+    fn parse_pool() {
+        let pool = |input| match parse(input) {
+            Ok(Expr::Dice(term)) => term.pool,
+            _ => panic!("{} is not a dice term", input),
+        };
+        let condition = |comparison, value| Condition { comparison, value };
+        assert_eq!(
+            pool("10d10>=8"),
+            Some(Pool {
+                success: condition(Comparison::GreaterOrEqual, 8),
+                failure: None,
+                double: None,
+            })
+        );
+        assert_eq!(
+            pool("10d10>7f<=1dbl10"),
+            Some(Pool {
+                success: condition(Comparison::Greater, 7),
+                failure: Some(condition(Comparison::LessOrEqual, 1)),
+                double: Some(condition(Comparison::Equal, 10)),
+            })
+        );
+        assert_eq!(pool("10D10>7F<=1DbL10"), pool("10d10>7f<=1dbl10"));
+        assert!(parse("10d10>7db").is_err());
+        assert!(parse("10d10f1").is_err());
+        assert!(parse("10d10dbl10").is_err());
+        assert!(parse("10d10>=8>=9").is_err());
+    }
+
+    #[test]
+    fn parse_fudge() {
+        let fudge = |count, fudge| Box::new(Expr::Dice(DiceTerm::fudge(count, fudge)));
+        assert_eq!(parse("4dF"), Ok(*fudge(4, Fudge::Standard)));
+        assert_eq!(parse("df.2"), Ok(*fudge(1, Fudge::Standard)));
+        assert_eq!(
+            parse("4dF.1+2"),
+            Ok(Expr::Binary(
+                BinOp::Add,
+                fudge(4, Fudge::Single),
+                Box::new(Expr::Number(2))
+            ))
+        );
+        let mut term = DiceTerm::fudge(3, Fudge::Standard);
+        term.reroll = Some(Reroll {
+            recursive: false,
+            condition: Condition {
+                comparison: Comparison::Less,
+                value: 0,
+            },
+        });
+        assert_eq!(parse("3dFr<0"), Ok(Expr::Dice(term)));
+        assert_eq!(syntax_error(parse("4dF.3")), "invalid Fudge dice variant");
+    }
+
+    #[test]
+    fn parse_repeat() {
+        let mut term = DiceTerm::new(4, 6);
+        term.keep = Some(Keep::Highest(3));
+        assert_eq!(
+            parse_statement("6x 4d6kh3 sort desc count>=15"),
+            Ok(Statement::from(Repeat {
+                times: 6,
+                expr: Expr::Dice(term),
+                sort: Some(Sort::Descending),
+                aggregate: Some(Aggregate::Count(Condition {
+                    comparison: Comparison::GreaterOrEqual,
+                    value: 15
+                })),
+            }))
+        );
+        assert_eq!(
+            parse_statement("3X(1d8+2) SUM"),
+            Ok(Statement::from(Repeat {
+                times: 3,
+                expr: Expr::Binary(BinOp::Add, dice(1, 8), Box::new(Expr::Number(2))),
+                sort: None,
+                aggregate: Some(Aggregate::Sum),
+            }))
+        );
+        assert_eq!(
+            parse_statement(" 12 "),
+            Ok(Statement::from(Expr::Number(12)))
+        );
+        assert_eq!(
+            syntax_error(parse_statement("2x 1d6 sum max")),
+            "rolls can only be aggregated once"
+        );
+        assert_eq!(
+            syntax_error(parse_statement("2x 1d6 summary")),
+            "unexpected trailing characters"
+        );
+        assert_eq!(
+            syntax_error(parse("6x 4d6")),
+            "unexpected trailing characters"
+        );
+    }
+
+    #[test]
+    fn parse_crit_thresholds() {
+        let mut term = DiceTerm::new(1, 20);
+        term.crit_success = Some(Condition {
+            comparison: Comparison::GreaterOrEqual,
+            value: 19,
+        });
+        term.crit_failure = Some(Condition {
+            comparison: Comparison::LessOrEqual,
+            value: 2,
+        });
+        assert_eq!(parse("1d20cs>=19cf<=2"), Ok(Expr::Dice(term.clone())));
+        assert_eq!(parse("1d20CF<=2CS>=19"), Ok(Expr::Dice(term)));
+        assert_eq!(
+            syntax_error(parse("1d20cs>=19cs20")),
+            "critical threshold can only be set once"
+        );
+        assert_eq!(
+            syntax_error(parse("1d20c20")),
+            "missing kind of critical threshold"
+        );
+    }
+
+    #[test]
+    fn parse_labels() {
+        let label = |expr, label: &str| Box::new(Expr::Label(expr, label.to_string()));
+        assert_eq!(
+            parse("2d6[fire] + 1d8 [ slashing ]"),
+            Ok(Expr::Binary(
+                BinOp::Add,
+                label(dice(2, 6), "fire"),
+                label(dice(1, 8), "slashing")
+            ))
+        );
+        assert_eq!(
+            parse("-(1d4+1)[cold]"),
+            Ok(Expr::Neg(label(
+                Box::new(Expr::Binary(
+                    BinOp::Add,
+                    dice(1, 4),
+                    Box::new(Expr::Number(1))
+                )),
+                "cold"
+            )))
+        );
+        let attack = Expr::Binary(BinOp::Add, dice(1, 20), Box::new(Expr::Number(5)));
+        assert_eq!(
+            parse_statement("1d20+5 # longsword attack"),
+            Ok(Statement {
+                kind: StatementKind::Roll(attack.clone()),
+                comment: Some("longsword attack".to_string()),
+            })
+        );
+        assert_eq!(parse("1d20+5 # longsword attack"), Ok(attack));
+        assert_eq!(
+            parse_statement("2d6[fire] # fireball"),
+            Ok(Statement {
+                kind: StatementKind::Roll(*label(dice(2, 6), "fire")),
+                comment: Some("fireball".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_statement("2x 1d6 sum # twice"),
+            Ok(Statement {
+                kind: StatementKind::Repeat(Repeat {
+                    times: 2,
+                    expr: *dice(1, 6),
+                    sort: None,
+                    aggregate: Some(Aggregate::Sum),
+                }),
+                comment: Some("twice".to_string()),
+            })
+        );
+        assert_eq!(syntax_error(parse("1d6[fire")), "missing closing bracket");
+        assert_eq!(syntax_error(parse("1d6[]")), "label can't be empty");
+    }
+
+    #[test]
+    fn parse_variables() {
+        let mut variables = HashMap::new();
+        variables.insert("str_mod".to_string(), "3".to_string());
+        variables.insert("prof".to_string(), "2".to_string());
+        variables.insert("smite".to_string(), "2d8 + @prof".to_string());
+        variables.insert("loop".to_string(), "1 + @loop".to_string());
+        variables.insert("broken".to_string(), "1 + @missing".to_string());
+        assert_eq!(
+            parse_with("1d20 + @str_mod + @prof", &variables),
+            Ok(Expr::Binary(
+                BinOp::Add,
+                Box::new(Expr::Binary(
+                    BinOp::Add,
+                    dice(1, 20),
+                    Box::new(Expr::Number(3))
+                )),
+                Box::new(Expr::Number(2))
+            ))
+        );
+        assert_eq!(
+            parse_with("2*@smite", &variables),
+            Ok(Expr::Binary(
+                BinOp::Mul,
+                Box::new(Expr::Number(2)),
+                Box::new(Expr::Binary(
+                    BinOp::Add,
+                    dice(2, 8),
+                    Box::new(Expr::Number(2))
+                ))
+            ))
+        );
+        assert_eq!(
+            parse_with("1d20 + @dex_mod", &variables),
+            Err(DiceError::UnknownVariable {
+                name: "dex_mod".to_string(),
+                position: 7
+            })
+        );
+        assert_eq!(
+            parse_with("1d6+@broken", &variables),
+            Err(DiceError::UnknownVariable {
+                name: "missing".to_string(),
+                position: 4
+            })
+        );
+        assert_eq!(
+            syntax_error(parse_with("@loop", &variables)),
+            "variable refers to itself"
+        );
+        assert_eq!(syntax_error(parse("1d6+@")), "missing variable name");
+    }
+
+    #[test]
+    fn parse_variables_within_limits() {
+        /// Counts the lookups of the variables it holds.
+        struct Counting(HashMap<String, String>, std::cell::Cell<usize>);
+        impl Variables for Counting {
+            fn get(&self, name: &str) -> Option<String> {
+                self.1.set(self.1.get() + 1);
+                self.0.get(name).cloned()
+            }
+        }
+
+        // Each variable is parsed once, however many times it is referenced.
+        let mut variables = HashMap::new();
+        variables.insert("v0".to_string(), "1d6".to_string());
+        for i in 1..=22 {
+            variables.insert(format!("v{}", i), format!("@v{} + @v{}", i - 1, i - 1));
+        }
+        let counting = Counting(variables, std::cell::Cell::new(0));
+        let expr = parse_with("@v5 * @v5", &counting).unwrap();
+        assert_eq!(size(&expr), (127, 63, 5));
+        assert_eq!(counting.1.get(), 6);
+
+        // Variables can't expand to an expression too large or too deep.
+        let error = |input, variables: &dyn Variables| match parse_with(input, variables) {
+            Err(DiceError::Syntax(error)) => (error.message, error.span),
+            result => panic!("{:?} is not a syntax error", result),
+        };
+        assert_eq!(
+            error("1 + @v22", &counting.0),
+            ("expression has too many operators", 4..8)
+        );
+        let mut variables = HashMap::new();
+        variables.insert("neg".to_string(), format!("{}1", "-".repeat(100)));
+        let sum = vec!["@neg"; 100].join(" + ");
+        assert_eq!(
+            error(&sum, &variables),
+            ("variables expand to too large an expression", 693..697)
+        );
+        variables.insert("v0".to_string(), "1".to_string());
+        for i in 1..=10_000 {
+            variables.insert(format!("v{}", i), format!("@v{}", i - 1));
+        }
+        assert_eq!(
+            error("@v10000", &variables),
+            ("expression nested too deeply", 0..7)
+        );
+        let deep = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        variables.insert("deep".to_string(), deep);
+        assert!(parse_with("@deep", &variables).is_ok());
+        let nested = format!("{}@deep{}", "(".repeat(50), ")".repeat(50));
+        assert_eq!(error(&nested, &variables).0, "expression nested too deeply");
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert!(parse("").is_err());
+        assert!(parse("2d").is_err());
+        assert!(parse("3d6+").is_err());
+        assert!(parse("(1d6").is_err());
+        let spanned = |error, span| Err(DiceError::Spanned(Box::new(error), span));
+        assert_eq!(parse("1d0"), spanned(DiceError::SidesOutOfRange(0), 2..3));
+        assert_eq!(
+            parse("1d4294967296"),
+            spanned(DiceError::SidesOutOfRange(4294967296), 2..12)
+        );
+        assert_eq!(parse("0d6"), spanned(DiceError::ZeroDice, 0..1));
+        assert_eq!(
+            parse("5000000000d6"),
+            spanned(DiceError::TooManyDice, 0..10)
+        );
+        assert_eq!(
+            parse("4d6kh5000000000"),
+            spanned(DiceError::TooManyDice, 5..15)
+        );
+        assert_eq!(
+            parse("99999999999999999999"),
+            spanned(DiceError::Overflow, 0..20)
+        );
+        assert_eq!(
+            parse("1+9223372036854775808"),
+            spanned(DiceError::Overflow, 2..21)
+        );
+        assert_eq!(
+            parse("5d10>=9223372036854775808"),
+            spanned(DiceError::Overflow, 6..25)
+        );
+        assert_eq!(
+            parse_statement("5000000000x 1d6").unwrap_err().span(),
+            Some(0..10)
+        );
+        assert!(parse("2dd8").is_err());
+    }
+
+    #[test]
+    fn parse_error_spans() {
+        let error = |input| match parse(input) {
+            Err(DiceError::Syntax(error)) => error,
+            result => panic!("{:?} is not a syntax error", result),
+        };
+        assert_eq!(
+            error("3d6+d"),
+            ParseError {
+                message: "right side of separator is not an int",
+                expected: Some("number of sides or 'F'"),
+                span: 5..5,
+            }
+        );
+        assert_eq!(error("2dd8").span, 2..3);
+        assert_eq!(error("(1d6 + 2").expected, Some("')'"));
+        assert_eq!(error("4d6kh3kl1").span, 6..9);
+        assert_eq!(error("1d6 [ ]").span, 4..7);
+        let nested = format!("{}1{}", "(".repeat(200), ")".repeat(200));
+        assert_eq!(
+            error(&nested),
+            ParseError {
+                message: "expression nested too deeply",
+                expected: None,
+                span: 128..129,
+            }
+        );
+        let negated = "-".repeat(10000);
+        assert_eq!(error(&negated).span, 128..129);
+        let chain = vec!["1d6"; MAX_OPERATORS + 2].join(" + ");
+        assert_eq!(
+            error(&chain),
+            ParseError {
+                message: "expression has too many operators",
+                expected: None,
+                span: 6004..6005,
+            }
+        );
+        assert!(parse(&vec!["1d6"; MAX_OPERATORS + 1].join("+")).is_ok());
+        let mut variables = HashMap::new();
+        variables.insert("bad".to_string(), "2dd8".to_string());
+        match parse_with("1 + @bad", &variables) {
+            Err(DiceError::Syntax(error)) => assert_eq!(error.span, 4..8),
+            result => panic!("{:?} is not a syntax error", result),
+        }
+    }
+
+    #[test]
+    fn render_errors() {
+        let render = |input| parse(input).unwrap_err().render(input);
+        assert_eq!(
+            render("3d6+d"),
+            "3d6+d\n     ^ right side of separator is not an int, expected number of sides or 'F'"
+        );
+        assert_eq!(
+            render("4d6kh3kl1"),
+            "4d6kh3kl1\n      ^^^ dice can only be kept or dropped once"
+        );
+        assert_eq!(
+            render("1d20 + @dex"),
+            "1d20 + @dex\n       ^^^^ Unknown variable @dex at position 7"
+        );
+        assert_eq!(
+            parse("2dd8").unwrap_err().to_string(),
+            "Argument malformed at position 2, right side of separator is not an int, \
+             expected number of sides or 'F'"
+        );
+        assert_eq!(render("0d6"), "0d6\n^ At least one die must be rolled");
+        assert_eq!(
+            render("2d6+1d0"),
+            "2d6+1d0\n      ^ A die can't have 0 sides"
+        );
+        assert_eq!(
+            parse("0d6").unwrap_err().to_string(),
+            "At least one die must be rolled"
+        );
+        let mut variables = HashMap::new();
+        variables.insert("hit".to_string(), "0d6".to_string());
+        let error = parse_with("1d20 + @hit", &variables).unwrap_err();
+        assert_eq!(error.span(), Some(7..11));
+        assert_eq!(error.unspanned(), &DiceError::ZeroDice);
+    }
+}
@@ -0,0 +1,227 @@
+//! Percentile rolls with Call of Cthulhu bonus and penalty dice.
+//!
+//! A percentile roll is a units die and a tens die, both from 0 to 9, read together as a value
+//! from 1 to 100, `00` and `0` reading 100. Each bonus die adds a tens die and the best value is
+//! kept, each penalty die adds one and the worst value is kept. Bonus and penalty dice cancel
+//! each other out.
+
+use crate::error::DiceError;
+use crate::roller::Limits;
+use rand::Rng;
+use std::fmt;
+
+/// A percentile roll with bonus or penalty dice.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Percentile {
+    pub bonus: u32,
+    pub penalty: u32,
+    pub limits: Limits,
+}
+
+/// The outcome of a percentile roll.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PercentileResult {
+    /// The units die, from 0 to 9.
+    pub units: u32,
+    /// Every tens die rolled, from 0 to 90.
+    pub tens: Vec<u32>,
+    /// Index in `tens` of the tens die kept.
+    pub kept: usize,
+    /// The value read, from 1 to 100.
+    pub value: u32,
+}
+
+/// How well a percentile roll did against a skill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Grade {
+    /// A 100, or 96 and above against a skill under 50.
+    Fumble,
+    /// Above the skill.
+    Failure,
+    /// At most the skill.
+    Regular,
+    /// At most half the skill.
+    Hard,
+    /// At most a fifth of the skill.
+    Extreme,
+    /// A 1.
+    Critical,
+}
+
+impl Percentile {
+    /// Creates a roll with no bonus or penalty die.
+    pub fn new() -> Percentile {
+        Default::default()
+    }
+
+    /// Adds bonus dice to the roll.
+    pub fn with_bonus(mut self, bonus: u32) -> Percentile {
+        self.bonus = self.bonus.saturating_add(bonus);
+        self
+    }
+
+    /// Adds penalty dice to the roll.
+    pub fn with_penalty(mut self, penalty: u32) -> Percentile {
+        self.penalty = self.penalty.saturating_add(penalty);
+        self
+    }
+
+    /// Rolls the percentile dice.
+    pub fn roll(&self) -> Result<PercentileResult, DiceError> {
+        self.roll_with(&mut rand::thread_rng())
+    }
+
+    /// Rolls the percentile dice using the given random number generator.
+    pub fn roll_with<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<PercentileResult, DiceError> {
+        let extra = self.bonus.max(self.penalty) - self.bonus.min(self.penalty);
+        if extra as u64 + 2 > self.limits.max_dice {
+            return Err(DiceError::TooManyDice);
+        }
+        let units = rng.gen_range(0, 10);
+        let tens: Vec<u32> = (0..=extra).map(|_| rng.gen_range(0, 10) * 10).collect();
+        let values = tens.iter().map(|&tens| read(tens, units));
+        let (kept, value) = if self.bonus >= self.penalty {
+            values.enumerate().min_by_key(|&(_, value)| value)
+        } else {
+            values.enumerate().max_by_key(|&(_, value)| value)
+        }
+        .expect("at least one tens die is rolled");
+        Ok(PercentileResult {
+            units,
+            tens,
+            kept,
+            value,
+        })
+    }
+}
+
+/// Reads a tens and a units die together, `00` and `0` reading 100.
+fn read(tens: u32, units: u32) -> u32 {
+    match tens + units {
+        0 => 100,
+        value => value,
+    }
+}
+
+impl PercentileResult {
+    /// Returns the grade of the roll against a skill value.
+    pub fn grade(&self, skill: u32) -> Grade {
+        if self.value == 1 {
+            Grade::Critical
+        } else if self.value == 100 || (skill < 50 && self.value >= 96) {
+            Grade::Fumble
+        } else if self.value <= skill / 5 {
+            Grade::Extreme
+        } else if self.value <= skill / 2 {
+            Grade::Hard
+        } else if self.value <= skill {
+            Grade::Regular
+        } else {
+            Grade::Failure
+        }
+    }
+
+    /// Returns true if the roll is at least a regular success against a skill value.
+    pub fn succeeds(&self, skill: u32) -> bool {
+        self.grade(skill) >= Grade::Regular
+    }
+}
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let grade = match self {
+            Grade::Fumble => "Fumble",
+            Grade::Failure => "Failure",
+            Grade::Regular => "Regular success",
+            Grade::Hard => "Hard success",
+            Grade::Extreme => "Extreme success",
+            Grade::Critical => "Critical success",
+        };
+        write!(f, "{}", grade)
+    }
+}
+
+impl fmt::Display for PercentileResult {
+    /// Writes the tens dice, discarded ones prefixed with `~`, the units die and the value,
+    /// e.g. `[~70, 30] + 4 = 34`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, tens) in self.tens.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            if i != self.kept {
+                write!(f, "~")?;
+            }
+            write!(f, "{:02}", tens)?;
+        }
+        write!(f, "] + {} = {}", self.units, self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn result(value: u32) -> PercentileResult {
+        PercentileResult {
+            units: value % 10,
+            tens: vec![value / 10 % 10 * 10],
+            kept: 0,
+            value,
+        }
+    }
+
+    #[test]
+    fn roll_keeps_best_or_worst_tens() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..100 {
+            let bonus = Percentile::new().with_bonus(2).roll_with(&mut rng).unwrap();
+            assert_eq!(bonus.tens.len(), 3);
+            let values = bonus.tens.iter().map(|&tens| read(tens, bonus.units));
+            assert_eq!(Some(bonus.value), values.min());
+
+            let penalty = Percentile::new()
+                .with_penalty(1)
+                .roll_with(&mut rng)
+                .unwrap();
+            assert_eq!(penalty.tens.len(), 2);
+            let values = penalty.tens.iter().map(|&tens| read(tens, penalty.units));
+            assert_eq!(Some(penalty.value), values.max());
+            assert!((1..=100).contains(&penalty.value));
+        }
+        let cancelled = Percentile::new()
+            .with_bonus(1)
+            .with_penalty(1)
+            .roll()
+            .unwrap();
+        assert_eq!(cancelled.tens.len(), 1);
+    }
+
+    #[test]
+    fn grades_against_skill() {
+        assert_eq!(result(1).grade(10), Grade::Critical);
+        assert_eq!(result(12).grade(60), Grade::Extreme);
+        assert_eq!(result(30).grade(60), Grade::Hard);
+        assert_eq!(result(60).grade(60), Grade::Regular);
+        assert_eq!(result(61).grade(60), Grade::Failure);
+        assert_eq!(result(97).grade(60), Grade::Failure);
+        assert_eq!(result(97).grade(40), Grade::Fumble);
+        assert_eq!(result(100).grade(90), Grade::Fumble);
+        assert!(result(45).succeeds(50));
+        assert_eq!(Grade::Hard.to_string(), "Hard success");
+    }
+
+    #[test]
+    fn display_percentile() {
+        let result = PercentileResult {
+            units: 4,
+            tens: vec![70, 30, 0],
+            kept: 1,
+            value: 34,
+        };
+        assert_eq!(result.to_string(), "[~70, 30, ~00] + 4 = 34");
+    }
+}
@@ -0,0 +1,437 @@
+//! Detailed results of rolling a dice expression.
+
+use crate::error::DiceError;
+use crate::expr::{Aggregate, BinOp, DiceTerm, Expr};
+use crate::fate;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::slice::Iter;
+
+/// The outcome of a single die.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DieResult {
+    pub value: i64,
+    /// False if the die was discarded by a keep or drop modifier.
+    pub kept: bool,
+    /// True if the die triggered an explosion.
+    pub exploded: bool,
+    /// True if the die is a critical success, by default when it rolled the highest face of a
+    /// die with several faces.
+    pub critical: bool,
+    /// True if the die is a critical failure, by default when it rolled the lowest face of a
+    /// die with several faces.
+    pub fumble: bool,
+    /// Every face rolled for a compounding die, empty for other dice.
+    pub faces: Vec<i64>,
+    /// Faces replaced by rerolls, in the order they were rolled.
+    pub rerolls: Vec<i64>,
+    /// True if the die counted as a success in a dice pool.
+    pub success: bool,
+    /// True if the die counted as a failure in a dice pool.
+    pub failure: bool,
+}
+
+impl DieResult {
+    /// Creates the result of a die that rolled `value`.
+    pub fn new(value: i64) -> DieResult {
+        DieResult {
+            value,
+            kept: true,
+            exploded: false,
+            critical: false,
+            fumble: false,
+            faces: Vec::new(),
+            rerolls: Vec::new(),
+            success: false,
+            failure: false,
+        }
+    }
+
+    /// Returns true if the die was rolled again by a reroll modifier.
+    pub fn rerolled(&self) -> bool {
+        !self.rerolls.is_empty()
+    }
+}
+
+/// The outcome of a dice term, with every die rolled for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermResult {
+    pub term: DiceTerm,
+    /// The innermost label around the term, if any.
+    pub label: Option<String>,
+    pub dice: Vec<DieResult>,
+    /// Net successes of a dice pool, `None` if the term sums its dice.
+    pub successes: Option<i64>,
+    pub total: i64,
+}
+
+/// The outcome of a whole expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollResult {
+    /// The expression that was rolled.
+    pub expr: Expr,
+    /// Rolled terms, in the order they appear in the expression.
+    pub terms: Vec<TermResult>,
+    /// The value of every labelled sub-expression, in the order they were evaluated.
+    pub labels: Vec<(String, i64)>,
+    pub total: i64,
+    /// The comment of the statement rolled, e.g. `longsword attack` for
+    /// `1d20+5 # longsword attack`.
+    pub comment: Option<String>,
+}
+
+/// The outcome of a repeated expression, e.g. `6x 4d6kh3`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListResult {
+    /// Every roll, in the order they were rolled or sorted by total.
+    pub rolls: Vec<RollResult>,
+    pub aggregate: Option<Aggregate>,
+    /// The aggregate of the totals of the rolls, `None` without an aggregate.
+    pub total: Option<i64>,
+    /// The comment of the statement rolled.
+    pub comment: Option<String>,
+}
+
+/// The outcome of a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementResult {
+    Roll(RollResult),
+    Repeat(ListResult),
+}
+
+impl StatementResult {
+    /// Returns the total of a single roll or the aggregate of a list of rolls.
+    pub fn total(&self) -> Option<i64> {
+        match self {
+            StatementResult::Roll(result) => Some(result.total),
+            StatementResult::Repeat(list) => list.total,
+        }
+    }
+}
+
+impl ListResult {
+    /// Returns the total of every roll, in order.
+    pub fn totals(&self) -> Vec<i64> {
+        self.rolls.iter().map(|roll| roll.total).collect()
+    }
+}
+
+impl RollResult {
+    /// Returns the dice that count towards the total.
+    pub fn kept(&self) -> impl Iterator<Item = &DieResult> {
+        self.terms
+            .iter()
+            .flat_map(|term| term.dice.iter())
+            .filter(|die| die.kept)
+    }
+
+    /// Returns true if the whole roll is a critical success.
+    ///
+    /// Only the terms with an explicit critical success threshold (`cs`) decide it, or the
+    /// first dice term if none has one, so that `1d20+8d6` is critical on a natural 20 but not
+    /// on a 6 rolled for damage. The roll is critical if a kept die of these terms is.
+    pub fn is_critical(&self) -> bool {
+        self.deciding(|term| term.crit_success.is_some())
+            .any(|die| die.critical)
+    }
+
+    /// Returns true if the whole roll is a critical failure.
+    ///
+    /// As for [`RollResult::is_critical`], only the terms with an explicit critical failure
+    /// threshold (`cf`) decide it, or the first dice term if none has one.
+    pub fn is_fumble(&self) -> bool {
+        self.deciding(|term| term.crit_failure.is_some())
+            .any(|die| die.fumble)
+    }
+
+    /// Returns the kept dice of the terms deciding whether the roll is critical: the terms
+    /// matching `explicit`, or the first term if none does.
+    fn deciding(&self, explicit: fn(&DiceTerm) -> bool) -> impl Iterator<Item = &DieResult> {
+        let has_explicit = self.terms.iter().any(|term| explicit(&term.term));
+        self.terms
+            .iter()
+            .enumerate()
+            .filter(move |&(i, term)| {
+                if has_explicit {
+                    explicit(&term.term)
+                } else {
+                    i == 0
+                }
+            })
+            .flat_map(|(_, term)| term.dice.iter())
+            .filter(|die| die.kept)
+    }
+
+    /// Returns the sum of the values of the sub-expressions with each label, e.g. the damage of
+    /// each type, or an error if a sum overflows.
+    pub fn label_totals(&self) -> Result<BTreeMap<&str, i64>, DiceError> {
+        let mut totals = BTreeMap::new();
+        for (label, value) in &self.labels {
+            let total = totals.entry(label.as_str()).or_insert(0i64);
+            *total = total.checked_add(*value).ok_or(DiceError::Overflow)?;
+        }
+        Ok(totals)
+    }
+
+    /// Returns the comment of the statement rolled, if any.
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    /// Returns the adjective of the Fate ladder for the total, e.g. "Great" for +4.
+    pub fn ladder(&self) -> &'static str {
+        fate::ladder(self.total)
+    }
+
+    /// Returns true if the expression rolls Fudge dice.
+    pub fn is_fudge(&self) -> bool {
+        self.terms.iter().any(|term| term.term.fudge.is_some())
+    }
+
+    /// Returns the dice discarded by keep and drop modifiers.
+    pub fn dropped(&self) -> impl Iterator<Item = &DieResult> {
+        self.terms
+            .iter()
+            .flat_map(|term| term.dice.iter())
+            .filter(|die| !die.kept)
+    }
+}
+
+impl fmt::Display for DieResult {
+    /// Writes the value of the die, prefixed with the faces it replaced (`1r4`) or the faces
+    /// it compounded (`6!6!3`).
+    ///
+    /// Dropped dice are prefixed with `~`, exploded dice are suffixed with `!`, and dice pool
+    /// successes and failures are suffixed with `*` and `f`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.kept {
+            write!(f, "~")?;
+        }
+        for reroll in &self.rerolls {
+            write!(f, "{}r", reroll)?;
+        }
+        if self.faces.len() > 1 {
+            let faces: Vec<String> = self.faces.iter().map(i64::to_string).collect();
+            write!(f, "{}", faces.join("!"))?;
+        } else {
+            write!(f, "{}", self.value)?;
+            if self.exploded {
+                write!(f, "!")?;
+            }
+        }
+        if self.success {
+            write!(f, "*")?;
+        }
+        if self.failure {
+            write!(f, "f")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TermResult {
+    /// Writes the dice of the term, e.g. `[6, 3, ~1]`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, die) in self.dice.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", die)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for RollResult {
+    /// Writes the expression with every dice term replaced by its dice, followed by the total
+    /// and the comment, e.g. `[6, 3, 1][fire] + 2 = 12 # fireball`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_breakdown(f, &self.expr, &mut self.terms.iter())?;
+        write!(f, " = {}", self.total)?;
+        if let Some(comment) = &self.comment {
+            write!(f, " # {}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ListResult {
+    /// Writes the breakdown of every roll followed by the aggregate, e.g.
+    /// `[6, 5, ~1] = 11; [4, 4, 3] = 11; sum = 22`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, roll) in self.rolls.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", roll)?;
+        }
+        if let (Some(aggregate), Some(total)) = (self.aggregate, self.total) {
+            write!(f, "; {} = {}", aggregate, total)?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, " # {}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for StatementResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatementResult::Roll(result) => write!(f, "{}", result),
+            StatementResult::Repeat(list) => write!(f, "{}", list),
+        }
+    }
+}
+
+/// Writes the operand of an operator, in parentheses if it binds less tightly.
+fn write_operand(
+    f: &mut fmt::Formatter<'_>,
+    operand: &Expr,
+    terms: &mut Iter<TermResult>,
+    parenthesize: bool,
+) -> fmt::Result {
+    if parenthesize {
+        write!(f, "(")?;
+        write_breakdown(f, operand, terms)?;
+        write!(f, ")")
+    } else {
+        write_breakdown(f, operand, terms)
+    }
+}
+
+/// Writes an expression, taking the rolled terms in order for its dice terms.
+fn write_breakdown(
+    f: &mut fmt::Formatter<'_>,
+    expr: &Expr,
+    terms: &mut Iter<TermResult>,
+) -> fmt::Result {
+    match expr {
+        Expr::Number(value) => write!(f, "{}", value),
+        Expr::Dice(term) => match terms.next() {
+            Some(result) => write!(f, "{}", result),
+            None => write!(f, "{}", term),
+        },
+        Expr::Neg(operand) => {
+            write!(f, "-")?;
+            write_operand(f, operand, terms, operand.precedence() < 3)
+        }
+        Expr::Label(operand, label) => {
+            write_operand(f, operand, terms, operand.is_labelled_in_parentheses())?;
+            write!(f, "[{}]", label)
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let op_precedence = expr.precedence();
+            write_operand(f, lhs, terms, lhs.precedence() < op_precedence)?;
+            let symbol = match op {
+                BinOp::Add => "+",
+                BinOp::Sub => "-",
+                BinOp::Mul => "*",
+                BinOp::Div => "/",
+            };
+            write!(f, " {} ", symbol)?;
+            write_operand(f, rhs, terms, rhs.precedence() <= op_precedence)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn roll_with_dice(input: &str, values: &[&[i64]]) -> RollResult {
+        let mut result = parse(input).unwrap().roll().unwrap();
+        for (term, values) in result.terms.iter_mut().zip(values) {
+            term.dice = values.iter().map(|&value| DieResult::new(value)).collect();
+        }
+        result
+    }
+
+    #[test]
+    fn display_breakdown() {
+        let mut result = roll_with_dice("3d6+2", &[&[6, 3, 1]]);
+        result.total = 12;
+        assert_eq!(result.to_string(), "[6, 3, 1] + 2 = 12");
+    }
+
+    #[test]
+    fn display_breakdown_parentheses() {
+        let mut result = roll_with_dice("(1d8+2)*-(1d4-1)", &[&[5], &[3]]);
+        result.total = -14;
+        assert_eq!(result.to_string(), "([5] + 2) * -([3] - 1) = -14");
+    }
+
+    #[test]
+    fn display_labels() {
+        let mut result = roll_with_dice("2d6[fire] + (1d4+1)[cold]", &[&[6, 3], &[2]]);
+        result.total = 12;
+        assert_eq!(result.to_string(), "[6, 3][fire] + ([2] + 1)[cold] = 12");
+        result.comment = Some("spell".to_string());
+        assert_eq!(
+            result.to_string(),
+            "[6, 3][fire] + ([2] + 1)[cold] = 12 # spell"
+        );
+        let mut result = roll_with_dice("((1d6)[a])[b] + (1d6[c]+2)[d]", &[&[4], &[5]]);
+        result.total = 11;
+        assert_eq!(result.to_string(), "([4][a])[b] + ([5][c] + 2)[d] = 11");
+    }
+
+    #[test]
+    fn critical_from_deciding_terms() {
+        let roll = |input, values: &[&[i64]]| {
+            let mut result = roll_with_dice(input, values);
+            for TermResult { term, dice, .. } in &mut result.terms {
+                for die in dice {
+                    die.critical = term.is_crit_success(die.value);
+                    die.fumble = term.is_crit_failure(die.value);
+                }
+            }
+            result
+        };
+        let result = roll("1d20+8d6", &[&[12], &[6, 1, 1, 1, 1, 1, 1, 1]]);
+        assert!(!result.is_critical() && !result.is_fumble());
+        let result = roll("1d20+2d6", &[&[20], &[3, 4]]);
+        assert!(result.is_critical() && !result.is_fumble());
+        let result = roll("1d6 + 1d20cs>=19", &[&[6], &[19]]);
+        assert!(result.is_critical() && !result.is_fumble());
+        let result = roll("1d6 + 1d20cs>=19", &[&[1], &[10]]);
+        assert!(!result.is_critical() && result.is_fumble());
+        let result = roll("1d1", &[&[1]]);
+        assert!(!result.is_critical() && !result.is_fumble());
+    }
+
+    #[test]
+    fn display_list() {
+        let mut first = roll_with_dice("3d6", &[&[6, 5, 1]]);
+        first.total = 12;
+        let mut second = roll_with_dice("3d6", &[&[4, 4, 3]]);
+        second.total = 11;
+        let mut list = ListResult {
+            rolls: vec![first, second],
+            aggregate: Some(Aggregate::Sum),
+            total: Some(23),
+            comment: None,
+        };
+        assert_eq!(list.to_string(), "[6, 5, 1] = 12; [4, 4, 3] = 11; sum = 23");
+        list.comment = Some("stats".to_string());
+        assert!(list.to_string().ends_with("; sum = 23 # stats"));
+    }
+
+    #[test]
+    fn display_die_flags() {
+        let mut die = DieResult::new(4);
+        die.rerolls = vec![1];
+        die.success = true;
+        assert_eq!(die.to_string(), "1r4*");
+        let mut die = DieResult::new(15);
+        die.faces = vec![6, 6, 3];
+        die.exploded = true;
+        assert_eq!(die.to_string(), "6!6!3");
+        let mut die = DieResult::new(6);
+        die.exploded = true;
+        die.kept = false;
+        assert_eq!(die.to_string(), "~6!");
+    }
+}
@@ -0,0 +1,695 @@
+//! Evaluation of dice expressions.
+
+use crate::error::DiceError;
+use crate::expr::{
+    BinOp, DiceTerm, Explode, ExplodeKind, Expr, Repeat, Reroll, Sort, Statement, StatementKind,
+};
+use crate::result::{DieResult, ListResult, RollResult, StatementResult, TermResult};
+use rand::distributions::Uniform;
+use rand::rngs::{StdRng, ThreadRng};
+use rand::{Rng, SeedableRng};
+
+/// Bounds applied to dice sets and expressions so that rolling them can't run forever or
+/// exhaust memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Maximum number of dice in a set or an expression, before explosions.
+    pub max_dice: u64,
+    /// Maximum number of sides of a die.
+    pub max_sides: u32,
+    /// Maximum number of extra dice rolled by the explosions of a single die.
+    pub max_explosions: u32,
+    /// Maximum number of rerolls of a single die.
+    pub max_rerolls: u32,
+    /// Maximum number of times an expression can be repeated.
+    pub max_repeats: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_dice: 16_843_009,
+            max_sides: 1_000_000,
+            max_explosions: 100,
+            max_rerolls: 100,
+            max_repeats: 100,
+        }
+    }
+}
+
+impl Limits {
+    /// Checks that a die has a number of sides within the limits.
+    pub fn check_sides(&self, sides: u32) -> Result<(), DiceError> {
+        if sides == 0 || sides > self.max_sides {
+            return Err(DiceError::SidesOutOfRange(sides as u64));
+        }
+        Ok(())
+    }
+
+    /// Checks that every term of an expression is within the limits, returning the number of
+    /// dice it rolls before explosions.
+    pub fn check(&self, expr: &Expr) -> Result<u64, DiceError> {
+        let dice = match expr {
+            Expr::Number(_) => 0,
+            Expr::Dice(term) => {
+                self.check_sides(term.sides)?;
+                term.count as u64
+            }
+            Expr::Neg(expr) | Expr::Label(expr, _) => self.check(expr)?,
+            Expr::Binary(_, lhs, rhs) => self
+                .check(lhs)?
+                .checked_add(self.check(rhs)?)
+                .ok_or(DiceError::TooManyDice)?,
+        };
+        if dice > self.max_dice {
+            return Err(DiceError::TooManyDice);
+        }
+        Ok(dice)
+    }
+
+    /// Checks that a statement is within the limits, returning the number of dice it rolls
+    /// before explosions.
+    pub fn check_statement(&self, statement: &Statement) -> Result<u64, DiceError> {
+        match &statement.kind {
+            StatementKind::Roll(expr) => self.check(expr),
+            StatementKind::Repeat(repeat) => {
+                if repeat.times > self.max_repeats {
+                    return Err(DiceError::TooManyRepeats);
+                }
+                match self.check(&repeat.expr)?.checked_mul(repeat.times as u64) {
+                    Some(dice) if dice <= self.max_dice => Ok(dice),
+                    _ => Err(DiceError::TooManyDice),
+                }
+            }
+        }
+    }
+}
+
+/// Rolls dice expressions with a random number generator, under a set of limits.
+///
+/// A roller created with [`Roller::seeded`] always produces the same sequence of rolls for the
+/// same seed, which makes it possible to replay a session.
+#[derive(Debug)]
+pub struct Roller<R = ThreadRng> {
+    rng: R,
+    pub limits: Limits,
+}
+
+impl Roller<ThreadRng> {
+    /// Creates a roller using the thread-local random number generator.
+    pub fn new() -> Self {
+        Roller::from_rng(rand::thread_rng())
+    }
+}
+
+impl Default for Roller<ThreadRng> {
+    fn default() -> Self {
+        Roller::new()
+    }
+}
+
+impl Roller<StdRng> {
+    /// Creates a roller whose sequence of rolls is determined by `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        Roller::from_rng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<R: Rng> Roller<R> {
+    /// Creates a roller using the given random number generator.
+    pub fn from_rng(rng: R) -> Self {
+        Roller {
+            rng,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Evaluates an expression, rolling every dice term once.
+    ///
+    /// The expression is checked against the limits of the roller before anything is rolled.
+    pub fn roll(&mut self, expr: &Expr) -> Result<RollResult, DiceError> {
+        self.limits.check(expr)?;
+        let mut trace = Trace::default();
+        let total = self.eval(expr, &mut trace, None)?;
+        Ok(RollResult {
+            expr: expr.clone(),
+            terms: trace.terms,
+            labels: trace.labels,
+            total,
+            comment: None,
+        })
+    }
+
+    /// Rolls a statement, either a single expression or a list of independent rolls, the
+    /// comment of the statement being kept in the result.
+    pub fn roll_statement(&mut self, statement: &Statement) -> Result<StatementResult, DiceError> {
+        self.limits.check_statement(statement)?;
+        let comment = statement.comment.clone();
+        match &statement.kind {
+            StatementKind::Roll(expr) => self
+                .roll(expr)
+                .map(|result| StatementResult::Roll(RollResult { comment, ..result })),
+            StatementKind::Repeat(repeat) => self
+                .roll_repeat(repeat)
+                .map(|list| StatementResult::Repeat(ListResult { comment, ..list })),
+        }
+    }
+
+    /// Rolls an expression the number of times of a repeat, then sorts and aggregates the
+    /// totals.
+    fn roll_repeat(&mut self, repeat: &Repeat) -> Result<ListResult, DiceError> {
+        let mut rolls = (0..repeat.times)
+            .map(|_| self.roll(&repeat.expr))
+            .collect::<Result<Vec<_>, _>>()?;
+        match repeat.sort {
+            Some(Sort::Ascending) => rolls.sort_by_key(|roll| roll.total),
+            Some(Sort::Descending) => rolls.sort_by_key(|roll| std::cmp::Reverse(roll.total)),
+            None => {}
+        }
+        let totals: Vec<i64> = rolls.iter().map(|roll| roll.total).collect();
+        let total = match repeat.aggregate {
+            Some(aggregate) => Some(aggregate.apply(&totals)?),
+            None => None,
+        };
+        Ok(ListResult {
+            rolls,
+            aggregate: repeat.aggregate,
+            total,
+            comment: None,
+        })
+    }
+
+    /// Evaluates an expression and records the rolled terms and labelled values, `label` being
+    /// the innermost label around the expression.
+    fn eval(
+        &mut self,
+        expr: &Expr,
+        trace: &mut Trace,
+        label: Option<&str>,
+    ) -> Result<i64, DiceError> {
+        match expr {
+            Expr::Number(value) => Ok(*value),
+            Expr::Dice(term) => {
+                let mut result = self.roll_term(term)?;
+                result.label = label.map(str::to_string);
+                let total = result.total;
+                trace.terms.push(result);
+                Ok(total)
+            }
+            Expr::Neg(expr) => self
+                .eval(expr, trace, label)?
+                .checked_neg()
+                .ok_or(DiceError::Overflow),
+            Expr::Label(expr, name) => {
+                let value = self.eval(expr, trace, Some(name))?;
+                trace.labels.push((name.clone(), value));
+                Ok(value)
+            }
+            Expr::Binary(..) => {
+                let (first, operations) = expr.chain();
+                let mut total = self.eval(first, trace, label)?;
+                for (op, rhs) in operations {
+                    let rhs = self.eval(rhs, trace, label)?;
+                    total = match op {
+                        BinOp::Add => total.checked_add(rhs).ok_or(DiceError::Overflow)?,
+                        BinOp::Sub => total.checked_sub(rhs).ok_or(DiceError::Overflow)?,
+                        BinOp::Mul => total.checked_mul(rhs).ok_or(DiceError::Overflow)?,
+                        BinOp::Div => {
+                            if rhs == 0 {
+                                return Err(DiceError::DivisionByZero);
+                            }
+                            total.checked_div(rhs).ok_or(DiceError::Overflow)?
+                        }
+                    };
+                }
+                Ok(total)
+            }
+        }
+    }
+
+    /// Rolls every die of a term and applies its modifiers.
+    fn roll_term(&mut self, term: &DiceTerm) -> Result<TermResult, DiceError> {
+        let die = Faces::new(term);
+        let mut dice: Vec<DieResult> = (0..term.count).map(|_| die.roll(&mut self.rng)).collect();
+
+        if let Some(reroll) = term.reroll {
+            self.reroll(&die, reroll, &mut dice);
+        }
+
+        if let Some(explode) = term.explode {
+            dice = self.explode(&die, explode, dice)?;
+        }
+
+        if let Some(keep) = term.keep {
+            let (discarded, lowest_first) = keep.discarded(dice.len());
+            let mut order: Vec<usize> = (0..dice.len()).collect();
+            order.sort_by_key(|&i| dice[i].value);
+            if !lowest_first {
+                order.reverse();
+            }
+            for &i in order.iter().take(discarded) {
+                dice[i].kept = false;
+            }
+        }
+
+        let successes = term.pool.map(|pool| {
+            let mut successes = 0;
+            for result in dice.iter_mut().filter(|die| die.kept) {
+                result.success = pool.success.matches(result.value);
+                result.failure = pool.is_failure(result.value);
+                successes += pool.score(result.value);
+            }
+            successes
+        });
+
+        let total = match successes {
+            Some(successes) => successes,
+            None => dice
+                .iter()
+                .filter(|die| die.kept)
+                .try_fold(0i64, |total, die| total.checked_add(die.value))
+                .ok_or(DiceError::Overflow)?,
+        };
+        Ok(TermResult {
+            term: term.clone(),
+            label: None,
+            dice,
+            successes,
+            total,
+        })
+    }
+
+    /// Rolls again the dice matching the reroll condition, keeping the replaced faces.
+    fn reroll(&mut self, die: &Faces, reroll: Reroll, dice: &mut [DieResult]) {
+        for result in dice.iter_mut() {
+            let mut remaining = self.limits.max_rerolls;
+            while reroll.condition.matches(result.value) && remaining > 0 {
+                remaining -= 1;
+                let rolled = die.roll(&mut self.rng);
+                result.rerolls.push(result.value);
+                result.value = rolled.value;
+                result.critical = rolled.critical;
+                result.fumble = rolled.fumble;
+                if !reroll.recursive {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Rolls extra dice for every die matching the explosion condition.
+    ///
+    /// Standard and penetrating explosions insert each extra roll right after the die that
+    /// triggered it, so a chain of explosions appears as consecutive dice. Compounding
+    /// explosions add the extra rolls to the triggering die and keep the chain in its faces.
+    fn explode(
+        &mut self,
+        die: &Faces,
+        explode: Explode,
+        dice: Vec<DieResult>,
+    ) -> Result<Vec<DieResult>, DiceError> {
+        let trigger = explode.trigger(die.term);
+        let mut exploded = Vec::with_capacity(dice.len());
+        for mut result in dice {
+            let mut remaining = self.limits.max_explosions;
+            let mut face = result.value;
+            match explode.kind {
+                ExplodeKind::Compound => {
+                    result.faces.push(face);
+                    while trigger.matches(face) && remaining > 0 {
+                        remaining -= 1;
+                        face = die.roll(&mut self.rng).value;
+                        result.faces.push(face);
+                        result.value = result.value.checked_add(face).ok_or(DiceError::Overflow)?;
+                        result.exploded = true;
+                    }
+                    exploded.push(result);
+                }
+                ExplodeKind::Standard | ExplodeKind::Penetrate => {
+                    while trigger.matches(face) && remaining > 0 {
+                        remaining -= 1;
+                        result.exploded = true;
+                        exploded.push(result);
+                        result = die.roll(&mut self.rng);
+                        face = result.value;
+                        if explode.kind == ExplodeKind::Penetrate {
+                            result.value -= 1;
+                        }
+                    }
+                    exploded.push(result);
+                }
+            }
+        }
+        Ok(exploded)
+    }
+}
+
+/// Terms and labelled values recorded while evaluating an expression.
+#[derive(Default)]
+struct Trace {
+    terms: Vec<TermResult>,
+    labels: Vec<(String, i64)>,
+}
+
+/// The faces of the dice of a term, sampled from a distribution built once for the whole term.
+struct Faces<'a> {
+    term: &'a DiceTerm,
+    range: Uniform<u32>,
+}
+
+impl<'a> Faces<'a> {
+    fn new(term: &'a DiceTerm) -> Faces<'a> {
+        Faces {
+            term,
+            range: Uniform::new_inclusive(1, term.sides),
+        }
+    }
+
+    /// Rolls a die once, flagging critical successes and failures.
+    fn roll<R: Rng>(&self, rng: &mut R) -> DieResult {
+        let value = self.term.face(rng.sample(self.range));
+        let mut result = DieResult::new(value);
+        result.critical = self.term.is_crit_success(value);
+        result.fumble = self.term.is_crit_failure(value);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, parse_statement};
+
+    #[test]
+    fn roll_seeded() {
+        let expr = parse("4d6kh3 + 3d10!>=9 + 10d10>=8").unwrap();
+        let first: Vec<RollResult> = {
+            let mut roller = Roller::seeded(1234);
+            (0..5).map(|_| roller.roll(&expr).unwrap()).collect()
+        };
+        let mut roller = Roller::seeded(1234);
+        for result in first {
+            assert_eq!(roller.roll(&expr).unwrap(), result);
+        }
+    }
+
+    #[test]
+    fn roll_keep_highest() {
+        let expr = parse("4d6kh3").unwrap();
+        for _ in 0..100 {
+            let result = expr.roll().unwrap();
+            let dice = &result.terms[0].dice;
+            let dropped: Vec<_> = dice.iter().filter(|die| !die.kept).collect();
+            assert_eq!(dropped.len(), 1);
+            assert!(dice.iter().all(|die| die.value >= dropped[0].value));
+            let kept_sum: i64 = dice
+                .iter()
+                .filter(|die| die.kept)
+                .map(|die| die.value)
+                .sum();
+            assert_eq!(result.total, kept_sum);
+        }
+    }
+
+    #[test]
+    fn roll_drop_more_than_rolled() {
+        let expr = parse("2d20dl5").unwrap();
+        let result = expr.roll().unwrap();
+        assert_eq!(result.total, 0);
+        assert!(result.terms[0].dice.iter().all(|die| !die.kept));
+    }
+
+    #[test]
+    fn roll_explode_is_capped() {
+        let mut roller = Roller::new();
+        roller.limits.max_explosions = 10;
+        let result = roller.roll(&parse("1d1!").unwrap()).unwrap();
+        assert_eq!(result.total, 11);
+        assert_eq!(result.terms[0].dice.len(), 11);
+        assert!(result.terms[0].dice[..10].iter().all(|die| die.exploded));
+        assert!(!result.terms[0].dice[10].exploded);
+
+        // The cap applies to each die, however many dice the term rolls.
+        let result = roller.roll(&parse("200d1!").unwrap()).unwrap();
+        assert_eq!(result.total, 2200);
+        let exploded = result.terms[0].dice.iter().filter(|die| die.exploded);
+        assert_eq!(exploded.count(), 2000);
+    }
+
+    #[test]
+    fn roll_compound_keeps_chain() {
+        let mut roller = Roller::new();
+        roller.limits.max_explosions = 3;
+        let result = roller.roll(&parse("2d1!!").unwrap()).unwrap();
+        let dice = &result.terms[0].dice;
+        assert_eq!(dice.len(), 2);
+        assert_eq!(dice[0].faces, vec![1, 1, 1, 1]);
+        assert_eq!(dice[0].value, 4);
+        assert_eq!(dice[1].faces, vec![1, 1, 1, 1]);
+        assert_eq!(result.total, 8);
+    }
+
+    #[test]
+    fn roll_penetrate_subtracts_one() {
+        let mut roller = Roller::new();
+        roller.limits.max_explosions = 2;
+        let result = roller.roll(&parse("1d1!p").unwrap()).unwrap();
+        let values: Vec<i64> = result.terms[0].dice.iter().map(|die| die.value).collect();
+        assert_eq!(values, vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn roll_reroll_once() {
+        let expr = parse("50d2r1").unwrap();
+        let result = expr.roll().unwrap();
+        for die in &result.terms[0].dice {
+            assert!(die.rerolls.len() <= 1);
+            assert_eq!(die.rerolled(), die.rerolls == vec![1]);
+        }
+    }
+
+    #[test]
+    fn roll_reroll_recursive() {
+        let expr = parse("10d3rr<=2").unwrap();
+        let result = expr.roll().unwrap();
+        for die in &result.terms[0].dice {
+            assert_eq!(die.value, 3);
+            assert!(die.rerolls.iter().all(|&face| face <= 2));
+        }
+    }
+
+    #[test]
+    fn roll_reroll_is_capped() {
+        let mut roller = Roller::new();
+        roller.limits.max_rerolls = 5;
+        let result = roller.roll(&parse("200d1rr1").unwrap()).unwrap();
+        let dice = &result.terms[0].dice;
+        assert!(dice.iter().all(|die| die.rerolls.len() == 5));
+        assert_eq!(result.total, 200);
+        let result = roller.roll(&parse("200d1ro1").unwrap()).unwrap();
+        let dice = &result.terms[0].dice;
+        assert!(dice.iter().all(|die| die.rerolls == vec![1]));
+    }
+
+    #[test]
+    fn roll_success_pool() {
+        let expr = parse("10d10>=8f1").unwrap();
+        for _ in 0..20 {
+            let result = expr.roll().unwrap();
+            let term = &result.terms[0];
+            for die in &term.dice {
+                assert_eq!(die.success, die.value >= 8);
+                assert_eq!(die.failure, die.value == 1);
+            }
+            let successes = term.dice.iter().filter(|die| die.success).count() as i64;
+            let failures = term.dice.iter().filter(|die| die.failure).count() as i64;
+            assert_eq!(term.successes, Some(successes - failures));
+            assert_eq!(result.total, successes - failures);
+        }
+    }
+
+    #[test]
+    fn roll_success_pool_doubles() {
+        let result = parse("6d1>=1dbl1").unwrap().roll().unwrap();
+        assert_eq!(result.total, 12);
+        let result = parse("6d1>=1dbl1+1").unwrap().roll().unwrap();
+        assert_eq!(result.total, 13);
+    }
+
+    #[test]
+    fn roll_flags_critical() {
+        let result = parse("20d1cs1").unwrap().roll().unwrap();
+        assert!(result.terms[0].dice.iter().all(|die| die.critical));
+        let result = parse("1d1!pcs1").unwrap().roll().unwrap();
+        assert!(result.terms[0].dice.iter().all(|die| die.critical));
+        let result = parse("20d1").unwrap().roll().unwrap();
+        assert!(result.kept().all(|die| !die.critical && !die.fumble));
+    }
+
+    #[test]
+    fn roll_crit_thresholds() {
+        let result = Roller::seeded(42)
+            .roll(&parse("50d20cs>=19cf<=2").unwrap())
+            .unwrap();
+        for die in &result.terms[0].dice {
+            assert_eq!(die.critical, die.value >= 19);
+            assert_eq!(die.fumble, die.value <= 2);
+        }
+        let result = parse("1d1").unwrap().roll().unwrap();
+        assert!(!result.is_critical() && !result.is_fumble());
+        let result = parse("2d20kh1cs<=0").unwrap().roll().unwrap();
+        assert!(!result.is_critical());
+        let result = parse("4dF").unwrap().roll().unwrap();
+        assert!(result.kept().all(|die| !die.critical && !die.fumble));
+        let result = parse("4dFcs1cf<0").unwrap().roll().unwrap();
+        for die in result.kept() {
+            assert_eq!(die.critical, die.value == 1);
+            assert_eq!(die.fumble, die.value == -1);
+        }
+    }
+
+    #[test]
+    fn roll_within_limits() {
+        let mut roller = Roller::new();
+        roller.limits.max_dice = 10;
+        roller.limits.max_sides = 100;
+        assert!(roller.roll(&parse("5d100+5d6").unwrap()).is_ok());
+        assert_eq!(
+            roller.roll(&parse("5d100+6d6").unwrap()),
+            Err(DiceError::TooManyDice)
+        );
+        assert_eq!(
+            roller.roll(&parse("1d101").unwrap()),
+            Err(DiceError::SidesOutOfRange(101))
+        );
+    }
+
+    #[test]
+    fn roll_large_dice() {
+        let result = parse("3d1000000").unwrap().roll().unwrap();
+        assert!((3..=3_000_000).contains(&result.total));
+        let mut roller = Roller::new();
+        roller.limits.max_sides = u32::MAX;
+        let result = roller.roll(&parse("3000000000d4294967295").unwrap());
+        assert_eq!(result, Err(DiceError::TooManyDice));
+    }
+
+    #[test]
+    fn roll_fudge() {
+        let result = parse("20dF.2+1").unwrap().roll().unwrap();
+        assert!(result.is_fudge());
+        assert!((-19..=21).contains(&result.total));
+        for die in result.kept() {
+            assert!((-1..=1).contains(&die.value));
+            assert!(!die.critical && !die.fumble);
+        }
+        let result = parse("20dF.1").unwrap().roll().unwrap();
+        assert!(result.kept().all(|die| (-1..=1).contains(&die.value)));
+        assert_eq!(result.ladder(), crate::fate::ladder(result.total));
+    }
+
+    #[test]
+    fn roll_repeat() {
+        let statement = parse_statement("6x 4d6kh3 sort desc sum").unwrap();
+        let list = match Roller::seeded(42).roll_statement(&statement).unwrap() {
+            StatementResult::Repeat(list) => list,
+            result => panic!("{} is not a list", result),
+        };
+        let totals = list.totals();
+        assert_eq!(totals.len(), 6);
+        assert!(totals.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(list.total, Some(totals.iter().sum()));
+        assert!(list.rolls.iter().all(|roll| roll.dropped().count() == 1));
+
+        let count = parse_statement("10x 1d20 count>=11")
+            .unwrap()
+            .roll()
+            .unwrap();
+        assert!((0..=10).contains(&count.total().unwrap()));
+        let plain = parse_statement("3x 1d6").unwrap().roll().unwrap();
+        assert_eq!(plain.total(), None);
+    }
+
+    #[test]
+    fn roll_repeat_within_limits() {
+        let mut roller = Roller::new();
+        roller.limits.max_dice = 20;
+        let statement = parse_statement("5x 4d6").unwrap();
+        assert!(roller.roll_statement(&statement).is_ok());
+        let statement = parse_statement("6x 4d6").unwrap();
+        assert_eq!(
+            roller.roll_statement(&statement),
+            Err(DiceError::TooManyDice)
+        );
+        let statement = parse_statement("101x 1").unwrap();
+        assert_eq!(
+            roller.roll_statement(&statement),
+            Err(DiceError::TooManyRepeats)
+        );
+
+        // Counts too large for a u64 are over any limit.
+        let limits = Limits {
+            max_dice: u64::MAX,
+            max_repeats: u32::MAX,
+            ..Limits::default()
+        };
+        let sum = ["4294967295d1"; 5].join("+");
+        let statement = parse_statement(&format!("4294967295x {}", sum)).unwrap();
+        assert_eq!(
+            limits.check_statement(&statement),
+            Err(DiceError::TooManyDice)
+        );
+    }
+
+    #[test]
+    fn roll_labels() {
+        let statement =
+            parse_statement("2d6[fire] + 1d8[slashing] + (1d4+1)[fire] + 3 # flaming sword")
+                .unwrap();
+        let result = match Roller::seeded(42).roll_statement(&statement).unwrap() {
+            StatementResult::Roll(result) => result,
+            result => panic!("{:?} is not a single roll", result),
+        };
+        let labels: Vec<Option<&str>> = result
+            .terms
+            .iter()
+            .map(|term| term.label.as_deref())
+            .collect();
+        assert_eq!(labels, vec![Some("fire"), Some("slashing"), Some("fire")]);
+        let totals = result.label_totals().unwrap();
+        let fire = result.terms[0].total + result.terms[2].total + 1;
+        assert_eq!(totals["fire"], fire);
+        assert_eq!(totals["slashing"], result.terms[1].total);
+        assert_eq!(totals.len(), 2);
+        assert_eq!(result.comment(), Some("flaming sword"));
+        assert!(result.to_string().ends_with(" # flaming sword"));
+
+        let result = Roller::seeded(42)
+            .roll(&parse("2d6[fire]").unwrap())
+            .unwrap();
+        assert_eq!(result.comment(), None);
+        assert_eq!(
+            result.to_string(),
+            format!("{}[fire] = {}", result.terms[0], result.total)
+        );
+
+        // Totals of labels overflow like the total of the roll.
+        let result = parse("9223372036854775807[a] - 1[b] + 1[a]")
+            .unwrap()
+            .roll()
+            .unwrap();
+        assert_eq!(result.total, i64::MAX);
+        assert_eq!(result.label_totals(), Err(DiceError::Overflow));
+    }
+
+    #[test]
+    fn roll_explode_on_condition() {
+        let expr = parse("20d10!>=9").unwrap();
+        for _ in 0..20 {
+            let result = expr.roll().unwrap();
+            for die in &result.terms[0].dice {
+                assert_eq!(die.exploded, die.value >= 9);
+            }
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Monte Carlo simulation of dice rolls.
+//!
+//! Some mechanics, such as custom dice or rerolls mixed with explosions, are awkward or
+//! impossible to compute exactly with a [`Distribution`](crate::Distribution). A [`Simulation`]
+//! rolls them many times instead and returns the empirical [`Histogram`] of their totals.
+//!
+//! Rolls are split in chunks of a fixed size, each with its own generator seeded from the seed
+//! of the simulation, so a seeded simulation gives the same histogram whether it runs in
+//! parallel or not.
+
+use crate::error::DiceError;
+use crate::expr::Expr;
+use crate::roller::{Limits, Roller};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Number of rolls made with each generator.
+const CHUNK: u64 = 4096;
+
+/// Width of the bars of a histogram written with `Display`.
+const CHART_WIDTH: usize = 40;
+
+/// A number of rolls to simulate, and how to roll them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Simulation {
+    pub rolls: u64,
+    /// Seed of the rolls, which are random if `None`.
+    pub seed: Option<u64>,
+    /// Whether to spread the rolls over every available core.
+    pub parallel: bool,
+    /// Limits applied to every roll of an expression.
+    pub limits: Limits,
+}
+
+/// The totals of a simulation, counted by value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    counts: BTreeMap<i64, u64>,
+    rolls: u64,
+}
+
+impl Simulation {
+    /// Creates a sequential simulation of `rolls` random rolls.
+    pub fn new(rolls: u64) -> Simulation {
+        Simulation {
+            rolls,
+            ..Default::default()
+        }
+    }
+
+    /// Seeds the rolls of the simulation.
+    pub fn with_seed(mut self, seed: u64) -> Simulation {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets whether the simulation runs on every available core.
+    pub fn with_parallel(mut self, parallel: bool) -> Simulation {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Rolls an expression the number of times of the simulation.
+    pub fn run(&self, expr: &Expr) -> Result<Histogram, DiceError> {
+        self.limits.check(expr)?;
+        self.sample(|rng| {
+            let mut roller = Roller::from_rng(rng);
+            roller.limits = self.limits.clone();
+            roller.roll(expr).map(|result| result.total)
+        })
+    }
+
+    /// Calls `roll` the number of times of the simulation and counts the values it returns,
+    /// which allows simulating rolls that aren't expressions, such as custom dice pools.
+    pub fn sample<F>(&self, roll: F) -> Result<Histogram, DiceError>
+    where
+        F: Fn(&mut StdRng) -> Result<i64, DiceError> + Sync,
+    {
+        if self.rolls == 0 {
+            return Err(DiceError::Malformed("at least one roll must be simulated"));
+        }
+        let mut seeder = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let chunks: Vec<(u64, u64)> = (0..self.rolls)
+            .step_by(CHUNK as usize)
+            .map(|start| (seeder.gen(), CHUNK.min(self.rolls - start)))
+            .collect();
+        let threads = if self.parallel {
+            thread::available_parallelism()
+                .map_or(1, NonZeroUsize::get)
+                .min(chunks.len())
+        } else {
+            1
+        };
+        log::info!("Simulating {} rolls on {} threads", self.rolls, threads);
+
+        let next = AtomicUsize::new(0);
+        let work = || {
+            let mut counts = BTreeMap::new();
+            while let Some(&(seed, rolls)) = chunks.get(next.fetch_add(1, Ordering::Relaxed)) {
+                let mut rng = StdRng::seed_from_u64(seed);
+                for _ in 0..rolls {
+                    match roll(&mut rng) {
+                        Ok(total) => *counts.entry(total).or_insert(0) += 1,
+                        Err(error) => {
+                            // Stop the other threads as well.
+                            next.store(chunks.len(), Ordering::Relaxed);
+                            return Err(error);
+                        }
+                    }
+                }
+            }
+            Ok(counts)
+        };
+        let partials: Vec<Result<BTreeMap<i64, u64>, DiceError>> = if threads > 1 {
+            thread::scope(|scope| {
+                let handles: Vec<_> = (0..threads).map(|_| scope.spawn(work)).collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("simulation thread panicked"))
+                    .collect()
+            })
+        } else {
+            vec![work()]
+        };
+
+        let mut counts = BTreeMap::new();
+        for partial in partials {
+            for (total, count) in partial? {
+                *counts.entry(total).or_insert(0) += count;
+            }
+        }
+        Ok(Histogram {
+            counts,
+            rolls: self.rolls,
+        })
+    }
+}
+
+/// Returns the two-sided critical value of the standard normal distribution for a confidence
+/// level between 0 and 1, using the rational approximation of Abramowitz and Stegun (26.2.23).
+fn critical_value(confidence: f64) -> Result<f64, DiceError> {
+    if !(confidence > 0.0 && confidence < 1.0) {
+        return Err(DiceError::Malformed("confidence must be between 0 and 1"));
+    }
+    let t = (-2.0 * ((1.0 - confidence) / 2.0).ln()).sqrt();
+    Ok(t - (2.515_517 + 0.802_853 * t + 0.010_328 * t * t)
+        / (1.0 + 1.432_788 * t + 0.189_269 * t * t + 0.001_308 * t * t * t))
+}
+
+impl Histogram {
+    /// Returns the number of rolls simulated.
+    pub fn rolls(&self) -> u64 {
+        self.rolls
+    }
+
+    /// Returns the number of rolls that totalled `value`.
+    pub fn count(&self, value: i64) -> u64 {
+        self.counts.get(&value).copied().unwrap_or(0)
+    }
+
+    /// Returns the smallest total rolled.
+    pub fn min(&self) -> i64 {
+        *self
+            .counts
+            .keys()
+            .next()
+            .expect("a simulation rolls at least once")
+    }
+
+    /// Returns the largest total rolled.
+    pub fn max(&self) -> i64 {
+        *self
+            .counts
+            .keys()
+            .next_back()
+            .expect("a simulation rolls at least once")
+    }
+
+    /// Returns the observed frequency of the value `value`.
+    pub fn probability(&self, value: i64) -> f64 {
+        self.count(value) as f64 / self.rolls as f64
+    }
+
+    /// Returns the observed frequency of a value greater than or equal to `target`.
+    pub fn at_least(&self, target: i64) -> f64 {
+        let count: u64 = self.counts.range(target..).map(|(_, count)| count).sum();
+        count as f64 / self.rolls as f64
+    }
+
+    /// Returns the observed frequency of a value less than or equal to `target`.
+    pub fn at_most(&self, target: i64) -> f64 {
+        let count: u64 = self.counts.range(..=target).map(|(_, count)| count).sum();
+        count as f64 / self.rolls as f64
+    }
+
+    /// Returns the mean of the totals.
+    pub fn mean(&self) -> f64 {
+        self.iter()
+            .map(|(value, count)| value as f64 * count as f64)
+            .sum::<f64>()
+            / self.rolls as f64
+    }
+
+    /// Returns the variance of the totals.
+    pub fn variance(&self) -> f64 {
+        let mean = self.mean();
+        self.iter()
+            .map(|(value, count)| (value as f64 - mean).powi(2) * count as f64)
+            .sum::<f64>()
+            / self.rolls as f64
+    }
+
+    /// Returns the standard deviation of the totals.
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Returns the smallest total whose cumulative frequency reaches `percentile`, given
+    /// between 0 and 1.
+    pub fn percentile(&self, percentile: f64) -> i64 {
+        let mut cumulative = 0;
+        for (value, count) in self.iter() {
+            cumulative += count;
+            if cumulative as f64 >= percentile * self.rolls as f64 {
+                return value;
+            }
+        }
+        self.max()
+    }
+
+    /// Returns the median total.
+    pub fn median(&self) -> i64 {
+        self.percentile(0.5)
+    }
+
+    /// Returns the confidence interval of the expected value at a confidence level between 0
+    /// and 1 exclusive, such as 0.95.
+    pub fn mean_interval(&self, confidence: f64) -> Result<(f64, f64), DiceError> {
+        let margin = critical_value(confidence)? * self.std_dev() / (self.rolls as f64).sqrt();
+        Ok((self.mean() - margin, self.mean() + margin))
+    }
+
+    /// Returns the Wilson score interval of the probability of the value `value` at a
+    /// confidence level between 0 and 1 exclusive.
+    pub fn probability_interval(
+        &self,
+        value: i64,
+        confidence: f64,
+    ) -> Result<(f64, f64), DiceError> {
+        self.wilson(self.probability(value), confidence)
+    }
+
+    /// Returns the Wilson score interval of the probability of a value greater than or equal
+    /// to `target` at a confidence level between 0 and 1 exclusive.
+    pub fn at_least_interval(&self, target: i64, confidence: f64) -> Result<(f64, f64), DiceError> {
+        self.wilson(self.at_least(target), confidence)
+    }
+
+    /// Returns the Wilson score interval of an observed frequency.
+    fn wilson(&self, frequency: f64, confidence: f64) -> Result<(f64, f64), DiceError> {
+        let z = critical_value(confidence)?;
+        let n = self.rolls as f64;
+        let center = frequency + z * z / (2.0 * n);
+        let margin = z * (frequency * (1.0 - frequency) / n + z * z / (4.0 * n * n)).sqrt();
+        let scale = 1.0 + z * z / n;
+        Ok((
+            ((center - margin) / scale).max(0.0),
+            ((center + margin) / scale).min(1.0),
+        ))
+    }
+
+    /// Returns every total rolled along with its number of rolls, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = (i64, u64)> + '_ {
+        self.counts.iter().map(|(&value, &count)| (value, count))
+    }
+
+    /// Renders the histogram as a bar chart, one line per total rolled, the most frequent
+    /// total having a bar of `width` characters, e.g.
+    ///
+    /// ```text
+    ///  1 | #####     33.33%
+    ///  2 | ##        13.33%
+    /// 10 | ########  53.33%
+    /// ```
+    pub fn chart(&self, width: usize) -> String {
+        let peak = self.counts.values().copied().max().unwrap_or(1);
+        let label_width = self
+            .counts
+            .keys()
+            .map(|value| value.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let mut chart = String::new();
+        for (value, count) in self.iter() {
+            let bar = (count as f64 / peak as f64 * width as f64).round() as usize;
+            chart.push_str(&format!(
+                "{:>label$} | {:<width$} {:6.2}%\n",
+                value,
+                "#".repeat(bar),
+                self.probability(value) * 100.0,
+                label = label_width,
+                width = width
+            ));
+        }
+        chart
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.chart(CHART_WIDTH))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::custom::{CustomDie, CustomPool, Face};
+    use crate::parser::parse;
+
+    #[test]
+    fn simulate_expression() {
+        let expr = parse("2d6").unwrap();
+        let simulation = Simulation::new(20_000).with_seed(42);
+        let histogram = simulation.run(&expr).unwrap();
+        assert_eq!(histogram.rolls(), 20_000);
+        assert_eq!(
+            histogram.iter().map(|(_, count)| count).sum::<u64>(),
+            20_000
+        );
+        assert_eq!((histogram.min(), histogram.max()), (2, 12));
+        assert!((histogram.mean() - 7.0).abs() < 0.1);
+        assert!((histogram.std_dev() - (35.0f64 / 6.0).sqrt()).abs() < 0.1);
+        assert_eq!(histogram.median(), 7);
+        let (low, high) = histogram.mean_interval(0.99).unwrap();
+        assert!(low < 7.0 && 7.0 < high);
+        let (low, high) = histogram.at_least_interval(10, 0.99).unwrap();
+        assert!(low < 1.0 / 6.0 && 1.0 / 6.0 < high);
+        for confidence in &[95.0, 1.0, 0.0, f64::NAN] {
+            assert_eq!(
+                histogram.mean_interval(*confidence),
+                Err(DiceError::Malformed("confidence must be between 0 and 1"))
+            );
+        }
+
+        let parallel = simulation.with_parallel(true).run(&expr).unwrap();
+        assert_eq!(parallel, histogram);
+        assert_eq!(
+            Simulation::new(0).run(&expr),
+            Err(DiceError::Malformed("at least one roll must be simulated"))
+        );
+    }
+
+    #[test]
+    fn simulate_custom_pool() {
+        let die = CustomDie::new("coin", vec![Face::number(0), Face::number(1)]).unwrap();
+        let mut pool = CustomPool::default();
+        pool.add_dice(&die, 3).unwrap();
+        let histogram = Simulation::new(10_000)
+            .with_seed(7)
+            .with_parallel(true)
+            .sample(|rng| pool.roll_with(rng).map(|result| result.total))
+            .unwrap();
+        assert_eq!((histogram.min(), histogram.max()), (0, 3));
+        let (low, high) = histogram.probability_interval(0, 0.95).unwrap();
+        assert!(low < 0.125 && 0.125 < high);
+    }
+
+    #[test]
+    fn chart_histogram() {
+        let histogram = Histogram {
+            counts: vec![(1, 5), (2, 2), (10, 8)].into_iter().collect(),
+            rolls: 15,
+        };
+        assert_eq!(
+            histogram.chart(8),
+            " 1 | #####     33.33%\n 2 | ##        13.33%\n10 | ########  53.33%\n"
+        );
+        assert!((critical_value(0.95).unwrap() - 1.96).abs() < 1e-3);
+    }
+}
@@ -37,6 +37,8 @@ pub fn rocket() -> rocket::Rocket {
                 routes::players::create_player_with_name,
                 routes::players::get_players,
                 routes::players::update_player_name,
+                routes::rolls::roll,
+                routes::rolls::odds,
             ],
         )
         .register(catchers![routes::not_found])
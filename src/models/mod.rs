@@ -1,2 +1,3 @@
 pub mod room;
 pub mod player;
+pub mod roll;
@@ -0,0 +1,35 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Deserialize)]
+pub struct RollRequest {
+    pub expression: String,
+    /// Seed making the roll reproducible, a random roll is made if absent.
+    pub seed: Option<u64>,
+    /// Values of the variables referenced in the expression, such as `@str_mod`.
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+impl fmt::Display for RollRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(Roll of {})", self.expression)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OddsRequest {
+    pub expression: String,
+    /// Value for which the probability of rolling at least as much is computed.
+    pub target: Option<i64>,
+    /// Values of the variables referenced in the expression, such as `@str_mod`.
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+impl fmt::Display for OddsRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(Odds of {})", self.expression)
+    }
+}
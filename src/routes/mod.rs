@@ -1,5 +1,6 @@
 pub mod rooms;
 pub mod players;
+pub mod rolls;
 use rocket_contrib::json::JsonValue;
 
 #[catch(404)]
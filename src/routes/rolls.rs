@@ -0,0 +1,143 @@
+use crate::models::roll;
+use dice_roller::{Analysis, DiceError, Distribution, Limits, RollResult, Roller, StatementResult};
+use rocket::response::status::BadRequest;
+use rocket_contrib::json::{Json, JsonValue};
+
+/// Highest complexity score of the expressions players can roll.
+const MAX_COMPLEXITY: u64 = 100_000;
+
+/// Builds the JSON body returned when an expression can't be rolled, with the position of the
+/// error in the expression and a diagnostic pointing at it.
+fn roll_error(expression: &str, error: DiceError) -> BadRequest<JsonValue> {
+    BadRequest(Some(json!({
+        "status": "Error",
+        "reason": error.to_string(),
+        "position": error.span().map(|span| span.start),
+        "diagnostic": error.render(expression)
+    })))
+}
+
+/// Builds the JSON description of every term of a roll.
+fn terms_json(result: &RollResult) -> Vec<JsonValue> {
+    result
+        .terms
+        .iter()
+        .map(|term| {
+            let dice: Vec<JsonValue> = term
+                .dice
+                .iter()
+                .map(|die| {
+                    json!({
+                        "value": die.value,
+                        "kept": die.kept,
+                        "exploded": die.exploded,
+                        "critical": die.critical,
+                        "fumble": die.fumble,
+                        "faces": die.faces,
+                        "rerolls": die.rerolls,
+                        "success": die.success,
+                        "failure": die.failure
+                    })
+                })
+                .collect();
+            json!({
+                "dice": dice,
+                "label": term.label,
+                "successes": term.successes,
+                "total": term.total
+            })
+        })
+        .collect()
+}
+
+/// Rejects the expressions whose analysis shows they are too complex for players to roll.
+fn check_complexity(analysis: Analysis) -> Result<Analysis, DiceError> {
+    if analysis.complexity > MAX_COMPLEXITY {
+        return Err(DiceError::TooComplex(analysis.complexity));
+    }
+    Ok(analysis)
+}
+
+#[post("/api/roll", format = "json", data = "<roll>")]
+pub fn roll(roll: Json<roll::RollRequest>) -> Result<JsonValue, BadRequest<JsonValue>> {
+    info!("Rolling {}", roll.0);
+    let roll_error = |error| roll_error(&roll.expression, error);
+    let statement =
+        dice_roller::parse_statement_with(&roll.expression, &roll.variables).map_err(roll_error)?;
+    Analysis::of_statement(&statement, &Limits::default())
+        .and_then(check_complexity)
+        .map_err(roll_error)?;
+    let result = match roll.seed {
+        Some(seed) => Roller::seeded(seed).roll_statement(&statement),
+        None => statement.roll(),
+    }
+    .map_err(roll_error)?;
+    let canonical = statement.normalize().to_string();
+    match result {
+        StatementResult::Roll(result) => {
+            let ladder = if result.is_fudge() {
+                Some(result.ladder())
+            } else {
+                None
+            };
+            let labels = result.label_totals().map_err(roll_error)?;
+            Ok(json!({
+                "expression": roll.0.expression,
+                "canonical": canonical,
+                "terms": terms_json(&result),
+                "breakdown": result.to_string(),
+                "seed": roll.0.seed,
+                "total": result.total,
+                "critical": result.is_critical(),
+                "fumble": result.is_fumble(),
+                "labels": labels,
+                "comment": result.comment(),
+                "ladder": ladder
+            }))
+        }
+        StatementResult::Repeat(list) => {
+            let rolls: Vec<JsonValue> = list
+                .rolls
+                .iter()
+                .map(|result| {
+                    json!({
+                        "terms": terms_json(result),
+                        "breakdown": result.to_string(),
+                        "total": result.total
+                    })
+                })
+                .collect();
+            Ok(json!({
+                "expression": roll.0.expression,
+                "canonical": canonical,
+                "rolls": rolls,
+                "breakdown": list.to_string(),
+                "seed": roll.0.seed,
+                "total": list.total,
+                "comment": list.comment
+            }))
+        }
+    }
+}
+
+#[post("/api/odds", format = "json", data = "<odds>")]
+pub fn odds(odds: Json<roll::OddsRequest>) -> Result<JsonValue, BadRequest<JsonValue>> {
+    info!("Computing {}", odds.0);
+    let roll_error = |error| roll_error(&odds.expression, error);
+    let expr = dice_roller::parse_with(&odds.expression, &odds.variables).map_err(roll_error)?;
+    Analysis::of(&expr, &Limits::default())
+        .and_then(check_complexity)
+        .map_err(roll_error)?;
+    let distribution = Distribution::of(&expr).map_err(roll_error)?;
+    let at_least = odds.target.map(|target| distribution.at_least(target));
+    Ok(json!({
+        "expression": odds.0.expression,
+        "min": distribution.min(),
+        "max": distribution.max(),
+        "mean": distribution.mean(),
+        "variance": distribution.variance(),
+        "median": distribution.median(),
+        "target": odds.0.target,
+        "at_least": at_least
+    }))
+}
@@ -17,4 +17,5 @@ pub fn response_json_value(response: &mut LocalResponse) -> JsonValue {
 }
 
 mod players;
+mod rolls;
 mod rooms;
@@ -0,0 +1,321 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+
+/// Helper function for posting an expression to the roll route.
+fn roll_route(client: &Client, expression: &str) -> rocket::local::LocalResponse {
+    client
+        .post("/api/roll")
+        .header(ContentType::JSON)
+        .body(format!(r#"{{"expression": "{}"}}"#, expression))
+        .dispatch()
+}
+
+#[test]
+fn roll_expression() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll an expression.
+    let mut response = roll_route(&client, "2d6+1d4+3");
+    assert_eq!(response.status(), Status::Ok);
+
+    // Check that this is a valid JSON (otherwise this function call would panic).
+    let response_json = super::response_json_value(&mut response);
+
+    // Ensure the total is within the bounds of the expression.
+    let total = response_json
+        .get("total")
+        .expect("must have a 'total' field")
+        .as_i64()
+        .unwrap();
+    assert!(total >= 6 && total <= 19);
+}
+
+#[test]
+fn roll_large_die() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll a die with as many sides as allowed.
+    let mut response = roll_route(&client, "1d1000000");
+    assert_eq!(response.status(), Status::Ok);
+
+    // Ensure the total is one of the faces of the die.
+    let response_json = super::response_json_value(&mut response);
+    let total = response_json
+        .get("total")
+        .expect("must have a 'total' field")
+        .as_i64()
+        .unwrap();
+    assert!(total >= 1 && total <= 1_000_000);
+}
+
+#[test]
+fn roll_canonical_expression() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll an expression written with like terms apart.
+    let mut response = roll_route(&client, "1d20 + 1d6 + 1d4 + 1d6 + 1 + 2 # damage");
+    assert_eq!(response.status(), Status::Ok);
+
+    // Ensure the expression is given in its canonical form, its comment included.
+    let response_json = super::response_json_value(&mut response);
+    let canonical = response_json
+        .get("canonical")
+        .expect("must have a 'canonical' field")
+        .as_str()
+        .unwrap();
+    assert_eq!(canonical, "1d20+2d6+1d4+3 # damage");
+}
+
+#[test]
+fn roll_malformed_expression() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll a malformed expression.
+    let mut response = roll_route(&client, "2dd8");
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Ensure the endpoint explains why the expression was rejected.
+    let response_json = super::response_json_value(&mut response);
+    let status = response_json
+        .get("status")
+        .expect("must have a 'status' field")
+        .as_str()
+        .unwrap();
+    assert_eq!(status, "Error");
+
+    // Ensure the endpoint points at the error in the expression.
+    let position = response_json
+        .get("position")
+        .expect("must have a 'position' field")
+        .as_u64()
+        .unwrap();
+    assert_eq!(position, 2);
+    let diagnostic = response_json
+        .get("diagnostic")
+        .expect("must have a 'diagnostic' field")
+        .as_str()
+        .unwrap();
+    assert!(diagnostic.starts_with("2dd8\n  ^ "));
+}
+
+#[test]
+fn roll_too_complex_expression() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll an expression too expensive to roll.
+    let mut response = roll_route(&client, "1000000d1000!");
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Ensure the endpoint rejects it for its complexity.
+    let response_json = super::response_json_value(&mut response);
+    let reason = response_json
+        .get("reason")
+        .expect("must have a 'reason' field")
+        .as_str()
+        .unwrap();
+    assert_eq!(reason, "Expression too complex to roll, complexity 202000000");
+}
+
+#[test]
+fn roll_expression_with_seed() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue the same seeded request twice.
+    let body = r#"{"expression": "4d6kh3", "seed": 42}"#;
+    let mut breakdowns = Vec::new();
+    for _ in 0..2 {
+        let mut response = client
+            .post("/api/roll")
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response_json = super::response_json_value(&mut response);
+        let breakdown = response_json
+            .get("breakdown")
+            .expect("must have a 'breakdown' field")
+            .as_str()
+            .unwrap()
+            .to_string();
+        breakdowns.push(breakdown);
+    }
+
+    // Ensure the seed reproduced the same roll.
+    assert_eq!(breakdowns[0], breakdowns[1]);
+}
+
+#[test]
+fn roll_fudge_expression() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll Fate dice with a skill bonus.
+    let mut response = roll_route(&client, "4dF+2");
+    assert_eq!(response.status(), Status::Ok);
+    let response_json = super::response_json_value(&mut response);
+
+    // Ensure the total is described on the Fate ladder.
+    let total = response_json
+        .get("total")
+        .expect("must have a 'total' field")
+        .as_i64()
+        .unwrap();
+    assert!(total >= -2 && total <= 6);
+    let ladder = response_json
+        .get("ladder")
+        .expect("must have a 'ladder' field")
+        .as_str()
+        .unwrap();
+    assert_eq!(ladder, dice_roller::fate::ladder(total));
+}
+
+#[test]
+fn roll_labelled_expression() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll damage of two types.
+    let mut response = roll_route(&client, "2d6[fire] + 1d8[slashing] # flaming sword");
+    assert_eq!(response.status(), Status::Ok);
+    let response_json = super::response_json_value(&mut response);
+
+    // Ensure each damage type is totalled separately.
+    let labels = response_json
+        .get("labels")
+        .expect("must have a 'labels' field");
+    let fire = labels.get("fire").unwrap().as_i64().unwrap();
+    let slashing = labels.get("slashing").unwrap().as_i64().unwrap();
+    assert!(fire >= 2 && fire <= 12);
+    assert!(slashing >= 1 && slashing <= 8);
+    let total = response_json
+        .get("total")
+        .expect("must have a 'total' field")
+        .as_i64()
+        .unwrap();
+    assert_eq!(total, fire + slashing);
+
+    // Ensure the comment is returned apart from the labels.
+    assert_eq!(labels.as_object().unwrap().len(), 2);
+    assert_eq!(response_json.get("comment").unwrap(), "flaming sword");
+}
+
+#[test]
+fn roll_expression_with_variables() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll an attack with the character's modifiers.
+    let body = r#"{"expression": "1d20 + @str_mod + @prof", "variables": {"str_mod": "3", "prof": "2"}}"#;
+    let mut response = client
+        .post("/api/roll")
+        .header(ContentType::JSON)
+        .body(body)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response_json = super::response_json_value(&mut response);
+    let total = response_json
+        .get("total")
+        .expect("must have a 'total' field")
+        .as_i64()
+        .unwrap();
+    assert!(total >= 6 && total <= 25);
+
+    // Ensure unknown variables are rejected.
+    let mut response = roll_route(&client, "1d20 + @dex_mod");
+    assert_eq!(response.status(), Status::BadRequest);
+    let response_json = super::response_json_value(&mut response);
+    let reason = response_json
+        .get("reason")
+        .expect("must have a 'reason' field")
+        .as_str()
+        .unwrap();
+    assert_eq!(reason, "Unknown variable @dex_mod at position 7");
+}
+
+#[test]
+fn roll_repeated_expression() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request to roll an array of ability scores.
+    let mut response = roll_route(&client, "6x 4d6kh3 sort desc sum");
+    assert_eq!(response.status(), Status::Ok);
+    let response_json = super::response_json_value(&mut response);
+
+    // Ensure every roll is returned, sorted, and summed in the total.
+    let rolls = response_json
+        .get("rolls")
+        .expect("must have a 'rolls' field")
+        .as_array()
+        .unwrap();
+    assert_eq!(rolls.len(), 6);
+    let totals: Vec<i64> = rolls
+        .iter()
+        .map(|roll| roll.get("total").unwrap().as_i64().unwrap())
+        .collect();
+    assert!(totals.windows(2).all(|pair| pair[0] >= pair[1]));
+    let total = response_json
+        .get("total")
+        .expect("must have a 'total' field")
+        .as_i64()
+        .unwrap();
+    assert_eq!(total, totals.iter().sum::<i64>());
+}
+
+#[test]
+fn odds_expression() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request for the odds of rolling at least 15 with 2d8+3.
+    let mut response = client
+        .post("/api/odds")
+        .header(ContentType::JSON)
+        .body(r#"{"expression": "2d8+3", "target": 15}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let response_json = super::response_json_value(&mut response);
+
+    // Ensure the endpoint returns the exact odds.
+    let at_least = response_json
+        .get("at_least")
+        .expect("must have an 'at_least' field")
+        .as_f64()
+        .unwrap();
+    assert!((at_least - 15.0 / 64.0).abs() < 1e-9);
+    let mean = response_json
+        .get("mean")
+        .expect("must have a 'mean' field")
+        .as_f64()
+        .unwrap();
+    assert!((mean - 12.0).abs() < 1e-9);
+}
+
+#[test]
+fn odds_too_many_dice() {
+    let _lock = super::DB_LOCK.lock();
+    let client = Client::new(crate::rocket()).expect("Rocket client");
+
+    // Issue a request for the odds of an expression rolling too many dice.
+    let mut response = client
+        .post("/api/odds")
+        .header(ContentType::JSON)
+        .body(r#"{"expression": "4294967295d6kh1"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // Ensure the endpoint rejects it with the limits of rolls.
+    let response_json = super::response_json_value(&mut response);
+    let reason = response_json
+        .get("reason")
+        .expect("must have a 'reason' field")
+        .as_str()
+        .unwrap();
+    assert_eq!(reason, "Maximum amount of dice reached");
+}